@@ -1,64 +1,567 @@
-use crate::path::audio_cache_key_path;
+use crate::audio_format;
+use crate::markup::has_markup;
+use crate::markup::parse_markup;
+use crate::markup::plain_text;
+use crate::markup::ssml;
+use crate::markup::ssml_pauses;
+use crate::markup::strip_ssml;
+use crate::markup::without_emphasis;
+use crate::markup::Markup;
+use crate::markup::SLOW_SPEED;
 use crate::path::audio_path;
+use crate::path::audio_segment_path;
+use crate::path::cache_key_path;
+use crate::path::cached_audio_path;
+use crate::pronunciation::pronounce_markup;
+use crate::pronunciation::pronounce_ssml;
+use crate::provider::TtsProvider;
 use crate::slide::Slide;
+use crate::subtitles::split_sentences;
+use crate::verbalize::verbalize_markup;
+use crate::verbalize::Language;
+use crate::video::probe_duration;
 use crate::Config;
+use chrono::NaiveTime;
+use futures::stream;
+use futures::StreamExt;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::json;
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
 use transformrs::text_to_speech::TTSConfig;
 use transformrs::Key;
 use transformrs::Keys;
 use transformrs::Provider;
 
+/// Everything that determines the generated audio.
+///
+/// The audio cache is content-addressed: audio is stored under the hash of
+/// this key. Inserting, removing, or reordering slides therefore does not
+/// invalidate the cache, and identical narration is shared between decks.
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
-struct AudioCacheKey {
+pub(crate) struct AudioCacheKey {
+    pub text: String,
+    pub config: TTSConfig,
+    pub model: Option<String>,
+    pub provider: String,
+    /// Entries of the pronunciation lexicon that were applied to the text.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pronunciations: BTreeMap<String, String>,
+}
+
+impl AudioCacheKey {
+    /// SHA-256 hash of the key in hexadecimal.
+    pub fn hash(&self) -> String {
+        // Converting to a `Value` first sorts the keys of the maps (such as
+        // `config.other`), which makes the hash independent of the order in
+        // which the keys were inserted.
+        let value = serde_json::to_value(self).unwrap();
+        let digest = Sha256::digest(serde_json::to_string(&value).unwrap().as_bytes());
+        format!("{digest:x}")
+    }
+}
+
+#[test]
+fn test_audio_cache_key_hash() {
+    let key = |text: &str, provider: &str| {
+        let mut other = HashMap::new();
+        for i in 0..10 {
+            other.insert(format!("key{i}"), json!(i));
+        }
+        AudioCacheKey {
+            text: text.to_string(),
+            config: TTSConfig {
+                other: Some(other),
+                ..Default::default()
+            },
+            model: None,
+            provider: provider.to_string(),
+            pronunciations: BTreeMap::new(),
+        }
+    };
+    let hash = key("foo", "DeepInfra").hash();
+    assert_eq!(hash.len(), 64);
+    for _ in 0..10 {
+        assert_eq!(key("foo", "DeepInfra").hash(), hash);
+    }
+    assert_ne!(key("bar", "DeepInfra").hash(), hash);
+    assert_ne!(key("foo", "Google").hash(), hash);
+}
+
+async fn write_cache_key(path: &Path, key: &AudioCacheKey) {
+    let key = serde_json::to_string(key).unwrap();
+    tokio::fs::write(path, key).await.unwrap();
+}
+
+/// Audio that has to be generated for (a part of) a slide.
+struct AudioRequest<'a> {
+    slide: &'a Slide,
     text: String,
+    audio_path: PathBuf,
     config: TTSConfig,
+    /// Entries of the pronunciation lexicon that were applied to `text`.
+    pronunciations: BTreeMap<String, String>,
+}
+
+impl AudioRequest<'_> {
+    fn cache_key(&self, provider: &TtsProvider, model: &Option<String>) -> AudioCacheKey {
+        AudioCacheKey {
+            text: self.text.clone(),
+            config: self.config.clone(),
+            model: model.clone(),
+            provider: provider.to_string(),
+            pronunciations: self.pronunciations.clone(),
+        }
+    }
+}
+
+/// A part of the audio of a slide.
+enum AudioPart<'a> {
+    /// Audio that is generated by the text-to-speech provider.
+    Speech(AudioRequest<'a>),
+    /// Silence, for example, for `[pause 1s]` or a slide with a duration.
+    Silence {
+        duration: chrono::Duration,
+        audio_path: PathBuf,
+    },
+    /// Pre-recorded audio that is used instead of text-to-speech.
+    Recording {
+        source: PathBuf,
+        audio_path: PathBuf,
+    },
+}
+
+impl AudioPart<'_> {
+    fn audio_path(&self) -> &Path {
+        match self {
+            AudioPart::Speech(request) => &request.audio_path,
+            AudioPart::Silence { audio_path, .. } | AudioPart::Recording { audio_path, .. } => {
+                audio_path
+            }
+        }
+    }
+}
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// A failed attempt to generate audio.
+enum Failure {
+    /// Rate limits, server errors, network errors, and invalid audio, which
+    /// may not occur again when the request is retried.
+    Transient(Error),
+    /// Errors that would occur again, such as an invalid key or request, or a
+    /// missing command.
+    Permanent(Error),
+}
+
+/// Whether the error response of the provider is a rate limit or server
+/// error.
+///
+/// transformrs does not expose the HTTP status, so the status is taken from
+/// the error in the response body. Responses that are not JSON, such as the
+/// HTML page of a gateway timeout, are also treated as server errors.
+fn is_transient_response(error: &Error) -> bool {
+    if error.is::<serde_json::Error>() {
+        return true;
+    }
+    let message = error.to_string();
+    let code = serde_json::from_str::<serde_json::Value>(&message)
+        .ok()
+        .and_then(|value| value.get("code")?.as_u64());
+    if let Some(code) = code {
+        return code == 429 || (500..600).contains(&code);
+    }
+    let message = message.to_lowercase();
+    [
+        "rate limit",
+        "rate_limit",
+        "too many requests",
+        "resource_exhausted",
+        "unavailable",
+        "overloaded",
+        "server_error",
+        "internal server error",
+    ]
+    .iter()
+    .any(|marker| message.contains(marker))
+}
+
+#[test]
+fn test_is_transient_response() {
+    let transient = |message: &str| is_transient_response(&message.into());
+    assert!(transient(
+        r#"{"code":429,"message":"Quota exceeded","status":"RESOURCE_EXHAUSTED"}"#
+    ));
+    assert!(transient(
+        r#"{"code":503,"message":"The service is unavailable"}"#
+    ));
+    assert!(transient(
+        r#"{"message":"Slow down","type":"requests","code":"rate_limit_exceeded"}"#
+    ));
+    assert!(!transient(
+        r#"{"code":400,"message":"Invalid voice","status":"INVALID_ARGUMENT"}"#
+    ));
+    assert!(!transient(
+        r#"{"message":"Incorrect API key provided","code":"invalid_api_key"}"#
+    ));
+    let not_json = serde_json::from_str::<serde_json::Value>("<html>").unwrap_err();
+    assert!(is_transient_response(&not_json.into()));
 }
 
-fn write_cache_key(dir: &str, slide: &Slide, config: &TTSConfig) {
-    let txt_path = audio_cache_key_path(dir, slide);
-    let mut file = File::create(txt_path).unwrap();
-    let text = slide.speaker_note.clone();
-    let cache_key = AudioCacheKey {
-        text,
-        config: config.clone(),
+/// Request the audio for the text from the provider.
+async fn synthesize(
+    provider: &Provider,
+    key: &Key,
+    config: &TTSConfig,
+    model: Option<&str>,
+    text: &str,
+) -> Result<Vec<u8>, Failure> {
+    // Fails when the request could not be sent or the response not read.
+    let resp = transformrs::text_to_speech::tts(provider, key, config, model, text)
+        .await
+        .map_err(Failure::Transient)?;
+    let speech = resp.structured().map_err(|e| {
+        if is_transient_response(&e) {
+            Failure::Transient(e)
+        } else {
+            Failure::Permanent(e)
+        }
+    })?;
+    Ok(speech.audio.to_vec())
+}
+
+/// Quote the value for use in a bash command.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Fill in the placeholders in the command template.
+///
+/// Supported placeholders are `{out}`, `{voice}`, `{speed}`, and
+/// `{language_code}`.
+fn command_line(template: &str, config: &TTSConfig, out: &Path) -> String {
+    let voice = config.voice.clone().unwrap_or_default();
+    let speed = config.speed.map(|s| s.to_string()).unwrap_or_default();
+    let language_code = config.language_code.clone().unwrap_or_default();
+    template
+        .replace("{out}", &shell_quote(out.to_str().unwrap()))
+        .replace("{voice}", &shell_quote(&voice))
+        .replace("{speed}", &shell_quote(&speed))
+        .replace("{language_code}", &shell_quote(&language_code))
+}
+
+#[test]
+fn test_command_line() {
+    let config = TTSConfig {
+        voice: Some("en_US-lessac-medium".to_string()),
+        ..Default::default()
     };
-    file.write_all(serde_json::to_string(&cache_key).unwrap().as_bytes())
-        .unwrap();
+    let out = Path::new("_out/audio/it's.wav");
+    let line = command_line("piper --model {voice} --output_file {out}", &config, out);
+    assert_eq!(
+        line,
+        "piper --model 'en_US-lessac-medium' --output_file '_out/audio/it'\\''s.wav'"
+    );
 }
 
-/// Whether the audio file for the given slide exists and is for the same slide.
-fn is_cached(dir: &str, slide: &Slide, config: &TTSConfig, audio_ext: &str) -> bool {
-    let txt_path = audio_cache_key_path(dir, slide);
-    let audio_path = audio_path(dir, slide, audio_ext);
-    if !txt_path.exists() || !audio_path.exists() {
-        return false;
+/// Run the local text-to-speech command with the text on stdin.
+async fn run_command(config: &TTSConfig, text: &str, out: &Path) -> Result<(), Error> {
+    // The command is stored in the config so that it is part of the cache key.
+    let template = config
+        .other
+        .as_ref()
+        .and_then(|other| other.get("command"))
+        .and_then(|command| command.as_str())
+        .ok_or("provider `command` requires `command` to be set in the trv config")?;
+    let line = command_line(template, config, out);
+    tracing::debug!("Running text-to-speech command: {line}");
+    let mut child = tokio::process::Command::new("/usr/bin/env")
+        .arg("bash")
+        .arg("-c")
+        .arg(&line)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(text.as_bytes()).await?;
+    drop(stdin);
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("`{line}` failed: {}", stderr.trim()).into());
     }
-    let stored_key = std::fs::read_to_string(txt_path).unwrap();
-    let stored_key = serde_json::from_str::<AudioCacheKey>(&stored_key).unwrap();
-    let text = slide.speaker_note.clone();
-    let cache_key = AudioCacheKey {
-        text,
-        config: config.clone(),
+    Ok(())
+}
+
+/// Duration of the mock audio for the text.
+///
+/// Assumes a speaking rate of 150 words per minute, scaled by the speed.
+fn mock_duration(text: &str, speed: Option<f64>) -> f64 {
+    let words = text.split_whitespace().count() as f64;
+    let seconds = words / 2.5 / speed.unwrap_or(1.0);
+    // Very short audio files are not handled well by ffmpeg.
+    seconds.max(0.5)
+}
+
+#[test]
+fn test_mock_duration() {
+    assert_eq!(mock_duration("one two three four five", None), 2.0);
+    assert_eq!(mock_duration("one two three four five", Some(2.0)), 1.0);
+    assert_eq!(mock_duration("", None), 0.5);
+}
+
+/// Generate silent audio with a duration that is based on the text.
+///
+/// The output is deterministic, so builds with the mock provider are
+/// reproducible.
+async fn generate_mock_audio(config: &TTSConfig, text: &str, out: &Path) -> Result<(), Error> {
+    generate_silence(mock_duration(text, config.speed), out).await
+}
+
+/// Write `duration` seconds of silence to `out`.
+async fn generate_silence(duration: f64, out: &Path) -> Result<(), Error> {
+    let output = tokio::process::Command::new("ffmpeg")
+        .arg("-y")
+        .arg("-f")
+        .arg("lavfi")
+        .arg("-i")
+        .arg("anullsrc=r=24000:cl=mono")
+        .arg("-t")
+        .arg(format!("{duration:.3}"))
+        .arg("-map_metadata")
+        .arg("-1")
+        .arg("-fflags")
+        .arg("+bitexact")
+        .arg(out)
+        .output()
+        .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("failed to generate silence: {}", stderr.trim()).into());
+    }
+    Ok(())
+}
+
+/// Transcode the recording at `source` into the audio file at `out`.
+///
+/// Recordings in the same format are transcoded as well, so that the
+/// metadata is removed and the output is the same for every build.
+async fn convert_recording(source: &Path, out: &Path) -> Result<(), Error> {
+    let output = tokio::process::Command::new("ffmpeg")
+        .arg("-y")
+        .arg("-i")
+        .arg(source)
+        .arg("-vn")
+        .arg("-map_metadata")
+        .arg("-1")
+        .arg("-fflags")
+        .arg("+bitexact")
+        .arg(out)
+        .output()
+        .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "failed to convert recording {}: {}",
+            source.display(),
+            stderr.trim()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Delay before retrying after the given (failed) attempt.
+///
+/// The delay grows exponentially with the number of attempts and is capped
+/// at 30 seconds. Jitter is added so that concurrent requests that failed at
+/// the same time do not all retry at the same time.
+fn backoff(attempt: u32) -> std::time::Duration {
+    let max = 1000_u64.saturating_mul(2_u64.saturating_pow(attempt - 1));
+    let max = max.min(30_000);
+    let half = max / 2;
+    let jitter = fastrand::u64(0..=half);
+    std::time::Duration::from_millis(half + jitter)
+}
+
+#[test]
+fn test_backoff() {
+    for _ in 0..100 {
+        let first = backoff(1).as_millis();
+        assert!((500..=1000).contains(&first));
+        let third = backoff(3).as_millis();
+        assert!((2000..=4000).contains(&third));
+        let last = backoff(100).as_millis();
+        assert!((15_000..=30_000).contains(&last));
+    }
+}
+
+/// Range of plausible durations for speaking the text.
+///
+/// Assumes about 15 characters per second, which is a typical speaking rate
+/// for English, but allows for a wide margin since the speed differs per
+/// voice, language, and `speed` setting.
+fn plausible_duration(text: &str, pauses: f64) -> (f64, f64) {
+    let expected = text.chars().count() as f64 / 15.0;
+    (expected / 5.0, expected * 6.0 + 3.0 + pauses)
+}
+
+#[test]
+fn test_plausible_duration() {
+    let text = "This sentence takes about two seconds to say.";
+    let (min, max) = plausible_duration(text, 0.0);
+    assert!(min < 2.0 && 2.0 < max);
+    let (min, _) = plausible_duration("", 0.0);
+    assert_eq!(min, 0.0);
+    // "Wait [pause 10s] now." takes about 11 seconds.
+    let (_, max) = plausible_duration("Wait now.", 0.0);
+    assert!(max < 11.0);
+    let (_, max) = plausible_duration("Wait now.", 10.0);
+    assert!(11.0 < max);
+}
+
+/// Path to which the audio is written before it is validated.
+fn unverified_path(path: &Path) -> PathBuf {
+    let ext = path.extension().unwrap_or_default().to_str().unwrap();
+    path.with_extension(format!("unverified.{ext}"))
+}
+
+/// Check that the audio is real audio for the text.
+///
+/// Some providers occasionally respond with an empty file or with an error
+/// message instead of audio. Such a response would otherwise end up in the
+/// cache and only crash later when ffmpeg combines the video.
+///
+/// `pauses` is the duration of the pauses in seconds, which are not part of
+/// the text when SSML is sent.
+async fn validate_audio(path: &Path, text: &str, pauses: f64) -> Result<(), Error> {
+    let len = tokio::fs::metadata(path).await?.len();
+    if len == 0 {
+        return Err("response is empty".into());
+    }
+    // ffprobe is run on a blocking thread, so that it does not block the
+    // other jobs.
+    let probe_path = path.to_path_buf();
+    let duration = tokio::task::spawn_blocking(move || probe_duration(&probe_path)).await?;
+    let duration = match duration {
+        Some(duration) => duration - NaiveTime::MIN,
+        None => return Err(format!("response of {len} bytes is not valid audio").into()),
     };
-    stored_key == cache_key
+    let seconds = duration.num_milliseconds() as f64 / 1000.0;
+    let (min, max) = plausible_duration(text, pauses);
+    if seconds < min || max < seconds {
+        return Err(format!(
+            "audio of {seconds:.1}s is implausible for {} characters of text \
+            (expected between {min:.1}s and {max:.1}s)",
+            text.chars().count()
+        )
+        .into());
+    }
+    Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn generate_audio_file(
-    provider: &Provider,
-    keys: &Keys,
-    dir: &str,
-    slide: &Slide,
-    cache: bool,
+/// Request the audio and write it to the unverified path if it is valid.
+///
+/// Audio that is missing or invalid is a transient failure. Errors of local
+/// commands are permanent, since running the command again would give the
+/// same result.
+async fn attempt_audio(
+    provider: &TtsProvider,
+    key: Option<&Key>,
+    request: &AudioRequest<'_>,
     config: &TTSConfig,
-    model: &Option<String>,
-    audio_ext: &str,
-) {
+    model: Option<&str>,
+) -> Result<PathBuf, Failure> {
+    let path = unverified_path(&request.audio_path);
+    match provider {
+        TtsProvider::Api(provider) => {
+            let key = key.unwrap();
+            let bytes = synthesize(provider, key, config, model, &request.text).await?;
+            tokio::fs::write(&path, &bytes)
+                .await
+                .map_err(|e| Failure::Permanent(e.into()))?;
+        }
+        TtsProvider::Command => run_command(config, &request.text, &path)
+            .await
+            .map_err(Failure::Permanent)?,
+        TtsProvider::Mock => generate_mock_audio(config, &request.text, &path)
+            .await
+            .map_err(Failure::Permanent)?,
+    }
+    if !tokio::fs::try_exists(&path).await.unwrap_or(false) {
+        let e = format!("no audio was written to {}", path.display());
+        return Err(Failure::Transient(e.into()));
+    }
+    let pauses = config
+        .other
+        .as_ref()
+        .and_then(|other| other.get("input")?.get("ssml")?.as_str())
+        .map_or(0.0, ssml_pauses);
+    if let Err(e) = validate_audio(&path, &request.text, pauses).await {
+        tokio::fs::remove_file(&path)
+            .await
+            .map_err(|e| Failure::Permanent(e.into()))?;
+        return Err(Failure::Transient(e));
+    }
+    Ok(path)
+}
+
+/// Request the audio, retrying transient failures such as rate limits.
+///
+/// Responses that are not valid audio are retried too. Other failures, such
+/// as an invalid key, stop the build immediately. Returns the path to the
+/// validated audio file.
+async fn synthesize_with_retries(
+    provider: &TtsProvider,
+    key: Option<&Key>,
+    request: &AudioRequest<'_>,
+    config: &TTSConfig,
+    model: Option<&str>,
+    max_attempts: u32,
+) -> PathBuf {
+    let idx = request.slide.idx;
+    let max_attempts = max_attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        match attempt_audio(provider, key, request, config, model).await {
+            Ok(path) => return path,
+            Err(Failure::Permanent(e)) => {
+                tracing::error!("Slide {idx}: Text-to-speech request to {provider} failed: {e}");
+                std::process::exit(1);
+            }
+            Err(Failure::Transient(e)) if attempt < max_attempts => {
+                let delay = backoff(attempt);
+                tracing::warn!(
+                    "Slide {idx}: Text-to-speech request to {provider} failed \
+                    (attempt {attempt}/{max_attempts}): {e}. Retrying in {:.1}s...",
+                    delay.as_secs_f64()
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(Failure::Transient(e)) => {
+                tracing::error!(
+                    "Slide {idx}: Text-to-speech request to {provider} failed \
+                    after {max_attempts} attempts: {e}"
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Key for the provider.
+///
+/// Local providers do not need a key.
+fn provider_key(keys: &Keys, provider: &TtsProvider) -> Option<Key> {
     fn get_key(keys: &Keys, provider: &Provider) -> Key {
         match keys.for_provider(provider) {
             Some(key) => key,
@@ -67,6 +570,10 @@ async fn generate_audio_file(
             }
         }
     }
+    let provider = match provider {
+        TtsProvider::Api(provider) => provider,
+        TtsProvider::Command | TtsProvider::Mock => return None,
+    };
     let key = match provider {
         Provider::OpenAICompatible(domain) => {
             // Yes the whole key and providers API from transformrs is a mess.
@@ -81,100 +588,701 @@ async fn generate_audio_file(
         }
         _ => get_key(keys, provider),
     };
-    let is_cached = cache && is_cached(dir, slide, config, audio_ext);
-    if is_cached {
-        tracing::info!(
-            "Slide {}: Skipping audio generation due to cache",
-            slide.idx
-        );
-        return;
-    }
-    let model = model.as_deref();
-    let text = &slide.speaker_note;
-    let resp = transformrs::text_to_speech::tts(provider, &key, config, model, text)
-        .await
-        .unwrap()
-        .structured()
-        .unwrap();
-    let bytes = resp.audio.clone();
-    let path = audio_path(dir, slide, audio_ext);
+    Some(key)
+}
+
+/// Generate the audio for the request.
+///
+/// If `cache_dir` is set, the audio is taken from the cache when possible and
+/// newly generated audio is added to the cache.
+async fn generate_audio_file(
+    provider: &TtsProvider,
+    keys: &Keys,
+    request: &AudioRequest<'_>,
+    cache_dir: Option<&Path>,
+    model: &Option<String>,
+    max_attempts: u32,
+) {
+    let path = &request.audio_path;
     if let Some(parent) = path.parent() {
-        if !parent.exists() {
-            std::fs::create_dir_all(parent).unwrap();
+        tokio::fs::create_dir_all(parent).await.unwrap();
+    }
+    let cache_key = request.cache_key(provider, model);
+    let hash = cache_key.hash();
+    let audio_ext = path.extension().unwrap().to_str().unwrap();
+    if let Some(cache_dir) = cache_dir {
+        let cached = cached_audio_path(cache_dir, &hash, audio_ext);
+        if tokio::fs::try_exists(&cached).await.unwrap() {
+            tracing::info!(
+                "Slide {}: Skipping audio generation due to cache",
+                request.slide.idx
+            );
+            tokio::fs::copy(cached, path).await.unwrap();
+            return;
         }
     }
-    let mut file = File::create(path).unwrap();
-    file.write_all(&bytes).unwrap();
-    if cache && !is_cached {
-        write_cache_key(dir, slide, config);
+    let key = provider_key(keys, provider);
+    let model = model.as_deref();
+    let config = &request.config;
+    let unverified =
+        synthesize_with_retries(provider, key.as_ref(), request, config, model, max_attempts).await;
+    tokio::fs::rename(unverified, path).await.unwrap();
+    if let Some(cache_dir) = cache_dir {
+        let cached = cached_audio_path(cache_dir, &hash, audio_ext);
+        tokio::fs::create_dir_all(cached.parent().unwrap())
+            .await
+            .unwrap();
+        // The key is written for inspecting the cache.
+        write_cache_key(&cache_key_path(cache_dir, &hash), &cache_key).await;
+        add_to_cache(path, &cached).await;
+    }
+}
+
+/// Copy the audio file into the cache.
+///
+/// The file is first copied to a uniquely named temporary file next to the
+/// entry and then renamed. Since the rename is atomic, an interrupted copy or
+/// another job caching the same audio never leaves a partial entry that later
+/// runs would use. Leftover temporary files share the hash of the entry, so
+/// `trv cache` removes them together with the entry.
+async fn add_to_cache(path: &Path, cached: &Path) {
+    let tmp = cached.with_extension(format!("{:016x}.tmp", fastrand::u64(..)));
+    tokio::fs::copy(path, &tmp).await.unwrap();
+    tokio::fs::rename(&tmp, cached).await.unwrap();
+}
+
+/// Concatenate multiple audio files into one file.
+async fn concat_audio(inputs: &[PathBuf], output: &Path) {
+    let mut cmd = tokio::process::Command::new("ffmpeg");
+    cmd.arg("-y");
+    for input in inputs {
+        cmd.arg("-i").arg(input);
+    }
+    // For example, `[0:a][1:a]concat=n=2:v=0:a=1[outa]`.
+    let streams = (0..inputs.len())
+        .map(|i| format!("[{i}:a]"))
+        .collect::<String>();
+    let filter = format!("{streams}concat=n={}:v=0:a=1[outa]", inputs.len());
+    cmd.arg("-filter_complex")
+        .arg(filter)
+        .arg("-map")
+        .arg("[outa]")
+        .arg(output);
+    tracing::debug!("FFmpeg command:\n{:?}", cmd);
+    let out = cmd.output().await.expect("Failed to run ffmpeg command");
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        tracing::error!("Failed to concat audio into {}: {stderr}", output.display());
+        std::process::exit(1);
+    }
+}
+
+/// Voice for the given speaker from the `[voices]` table in the trv config.
+fn speaker_voice(config: &Config, slide: &Slide, speaker: &str) -> String {
+    match config.voices.get(speaker) {
+        Some(voice) => voice.clone(),
+        None => panic!(
+            "Slide {}: no voice for speaker `{speaker}` in the [voices] table of the trv config",
+            slide.idx
+        ),
     }
 }
 
-fn tts_config(config: &Config, provider: &Provider) -> TTSConfig {
+/// Text-to-speech config for the given slide.
+///
+/// Settings from the front matter of the slide take precedence over the trv
+/// config. Since the result is part of the `AudioCacheKey`, changing an
+/// override only regenerates the audio for the affected slide.
+fn tts_config(config: &Config, provider: &TtsProvider, slide: &Slide) -> TTSConfig {
+    let overrides = &slide.config;
+    let seed = overrides.seed.or(config.seed);
     let mut other = HashMap::new();
     if provider != &Provider::Google {
-        other.insert("seed".to_string(), json!(42));
+        // transformrs only sends `TTSConfig::seed` to ElevenLabs, so the seed
+        // is passed via the request body for all providers.
+        other.insert("seed".to_string(), json!(seed.unwrap_or(42)));
+    }
+    if provider == &TtsProvider::Command {
+        other.insert("command".to_string(), json!(config.command));
     }
     TTSConfig {
-        voice: Some(config.voice.clone()),
+        voice: Some(overrides.voice.clone().unwrap_or(config.voice.clone())),
         output_format: config.audio_format.clone(),
-        speed: config.speed,
-        seed: config.seed,
+        speed: overrides.speed.or(config.speed),
+        seed,
         other: Some(other),
-        language_code: config.language_code.clone(),
+        language_code: overrides
+            .language_code
+            .clone()
+            .or(config.language_code.clone()),
     }
 }
 
-fn set_previous_and_next_text(tts_config: &mut TTSConfig, slides: &[Slide], idx: usize) {
+#[test]
+fn test_tts_config_overrides() {
+    let config = Config {
+        voice: "am_adam".to_string(),
+        speed: Some(1.0),
+        ..Default::default()
+    };
+    let mut slide = Slide {
+        idx: 1,
+        speaker_note: "foo".to_string(),
+        config: Default::default(),
+        speakers: Vec::new(),
+    };
+    let tts = tts_config(&config, &TtsProvider::Api(Provider::DeepInfra), &slide);
+    assert_eq!(tts.voice, Some("am_adam".to_string()));
+    assert_eq!(tts.speed, Some(1.0));
+
+    assert_eq!(tts.other.as_ref().unwrap()["seed"], json!(42));
+
+    slide.config.voice = Some("af_bella".to_string());
+    slide.config.seed = Some(7);
+    let tts = tts_config(&config, &TtsProvider::Api(Provider::DeepInfra), &slide);
+    assert_eq!(tts.voice, Some("af_bella".to_string()));
+    assert_eq!(tts.speed, Some(1.0));
+    assert_eq!(tts.other.as_ref().unwrap()["seed"], json!(7));
+    let tts = tts_config(&config, &TtsProvider::Api(Provider::ElevenLabs), &slide);
+    assert_eq!(tts.other.as_ref().unwrap()["seed"], json!(7));
+}
+
+fn set_previous_and_next_text(tts_config: &mut TTSConfig, slides: &[Slide], slide: &Slide) {
     let other = tts_config.other.as_mut().unwrap();
-    let n = slides.len();
-    if 1 < idx {
-        // slides is 0-indexed, while idx is 1-indexed.
-        let i = (idx - 1) - 1;
-        other.insert(
-            "previous_text".to_string(),
-            json!(slides[i].speaker_note.clone()),
-        );
+    // Not using the idx since hidden slides are not in `slides`.
+    let i = slides.iter().position(|s| s.idx == slide.idx).unwrap();
+    if 0 < i {
+        let text = slides[i - 1].plain_text();
+        other.insert("previous_text".to_string(), json!(text));
+    }
+    if i + 1 < slides.len() {
+        let text = slides[i + 1].plain_text();
+        other.insert("next_text".to_string(), json!(text));
     }
-    if idx < n {
-        // slides is 0-indexed, while idx is 1-indexed.
-        let i = (idx + 1) - 1;
-        other.insert(
-            "next_text".to_string(),
-            json!(slides[i].speaker_note.clone()),
+}
+
+/// Read the text slower for `[slow]` markup.
+fn slow_down(tts_config: &mut TTSConfig, provider: &TtsProvider, slide: &Slide) {
+    if provider == &Provider::ElevenLabs {
+        // ElevenLabs only supports setting the speed via the voice settings.
+        tracing::warn!(
+            "Slide {}: [slow] is not supported by ElevenLabs and is read at normal speed",
+            slide.idx
         );
+        return;
     }
+    tts_config.speed = Some(tts_config.speed.unwrap_or(1.0) * SLOW_SPEED);
 }
 
-pub async fn generate_audio_files(
-    provider: &Provider,
+/// Default maximum number of characters per text-to-speech request.
+const MAX_CHARS: usize = 2000;
+
+/// Pause between two chunks of a note that are split at a paragraph.
+const PARAGRAPH_PAUSE_MS: i64 = 600;
+
+/// Split a long text into chunks of at most `max_chars` characters.
+///
+/// The text is split at sentences, and at words for sentences that are too
+/// long. Returns the chunks and whether a paragraph ends after the chunk.
+/// The last chunk never ends a paragraph.
+fn chunk_text(text: &str, max_chars: usize) -> Vec<(String, bool)> {
+    let len = |text: &str| text.chars().count();
+    let mut units = Vec::new();
+    for paragraph in text.split("\n\n") {
+        let sentences = split_sentences(paragraph);
+        let count = sentences.len();
+        for (i, sentence) in sentences.into_iter().enumerate() {
+            let ends_paragraph = i + 1 == count;
+            if len(&sentence) <= max_chars {
+                units.push((sentence, ends_paragraph));
+                continue;
+            }
+            let words = sentence.split_whitespace().collect::<Vec<&str>>();
+            for (j, word) in words.iter().enumerate() {
+                units.push((word.to_string(), ends_paragraph && j + 1 == words.len()));
+            }
+        }
+    }
+    let mut chunks: Vec<(String, bool)> = Vec::new();
+    for (unit, ends_paragraph) in units {
+        if let Some((chunk, chunk_ends_paragraph)) = chunks.last_mut() {
+            let separator = if *chunk_ends_paragraph { "\n\n" } else { " " };
+            if len(chunk) + len(separator) + len(&unit) <= max_chars {
+                chunk.push_str(separator);
+                chunk.push_str(&unit);
+                *chunk_ends_paragraph = ends_paragraph;
+                continue;
+            }
+        }
+        chunks.push((unit, ends_paragraph));
+    }
+    if let Some((_, ends_paragraph)) = chunks.last_mut() {
+        *ends_paragraph = false;
+    }
+    chunks
+}
+
+#[test]
+fn test_chunk_text() {
+    let text = "One two. Three four.\n\nFive six seven eight nine ten eleven.\n\nEnd.";
+    assert_eq!(
+        chunk_text(text, 20),
+        vec![
+            ("One two. Three four.".to_string(), true),
+            ("Five six seven eight".to_string(), false),
+            ("nine ten eleven.".to_string(), true),
+            ("End.".to_string(), false),
+        ]
+    );
+    assert_eq!(
+        chunk_text("A. B.\n\nC.", 100),
+        vec![("A. B.\n\nC.".to_string(), false)]
+    );
+}
+
+/// Split the long text parts of the markup into chunks of at most
+/// `max_chars` characters, with a pause where a paragraph ends between two
+/// chunks.
+fn chunk_markup(markup: Vec<Markup>, max_chars: usize) -> Vec<Markup> {
+    let mut result = Vec::new();
+    for part in markup {
+        let (text, wrap): (String, fn(String) -> Markup) = match part {
+            Markup::Text(text) => (text, Markup::Text),
+            Markup::Slow(text) => (text, Markup::Slow),
+            Markup::Emphasis(text) => (text, Markup::Emphasis),
+            Markup::Pause(_) => {
+                result.push(part);
+                continue;
+            }
+        };
+        if text.chars().count() <= max_chars {
+            result.push(wrap(text));
+            continue;
+        }
+        for (chunk, ends_paragraph) in chunk_text(&text, max_chars) {
+            result.push(wrap(chunk));
+            if ends_paragraph {
+                result.push(Markup::Pause(chrono::Duration::milliseconds(
+                    PARAGRAPH_PAUSE_MS,
+                )));
+            }
+        }
+    }
+    result
+}
+
+/// Group consecutive parts so that the text of each group has at most
+/// `max_chars` characters.
+///
+/// Used to send markup as SSML in as few requests as possible.
+fn group_markup(markup: Vec<Markup>, max_chars: usize) -> Vec<Vec<Markup>> {
+    let mut groups: Vec<(Vec<Markup>, usize)> = Vec::new();
+    for part in markup {
+        let len = plain_text(std::slice::from_ref(&part)).chars().count();
+        if let Some((group, group_len)) = groups.last_mut() {
+            if *group_len + 1 + len <= max_chars {
+                group.push(part);
+                *group_len += 1 + len;
+                continue;
+            }
+        }
+        groups.push((vec![part], len));
+    }
+    groups.into_iter().map(|(group, _)| group).collect()
+}
+
+#[test]
+fn test_chunk_markup() {
+    let markup =
+        parse_markup("One two. Three four. [pause 1s] [slow]Five six. Seven.[/slow]").unwrap();
+    let chunks = chunk_markup(markup, 10);
+    assert_eq!(
+        chunks,
+        vec![
+            Markup::Text("One two.".to_string()),
+            Markup::Text("Three".to_string()),
+            Markup::Text("four.".to_string()),
+            Markup::Pause(chrono::Duration::seconds(1)),
+            Markup::Slow("Five six.".to_string()),
+            Markup::Slow("Seven.".to_string()),
+        ]
+    );
+    let groups = group_markup(chunks, 20);
+    assert_eq!(groups.len(), 2);
+    assert_eq!(plain_text(&groups[0]), "One two. Three four.");
+}
+
+/// The parts of the audio of the slide.
+///
+/// This is one request for most slides. Slides with multiple speakers get one
+/// request per segment, and long notes are split into requests of at most
+/// `max_chars` characters. For providers that don't support SSML, the text
+/// is also split at the pause and emphasis markup, and pauses are generated
+/// as silence.
+fn audio_parts<'a>(
+    provider: &TtsProvider,
+    dir: &str,
+    slide: &'a Slide,
+    slides: &[Slide],
+    config: &Config,
+    audio_ext: &str,
+) -> Result<Vec<AudioPart<'a>>, String> {
+    if let Some(duration) = slide.silent_duration() {
+        let audio_path = audio_path(dir, slide, audio_ext);
+        return Ok(vec![AudioPart::Silence {
+            duration,
+            audio_path,
+        }]);
+    }
+    if let Some(source) = &slide.config.audio {
+        let audio_path = audio_path(dir, slide, audio_ext);
+        return Ok(vec![AudioPart::Recording {
+            source: source.clone(),
+            audio_path,
+        }]);
+    }
+    let mut tts_config = tts_config(config, provider, slide);
+    if provider == &Provider::ElevenLabs {
+        set_previous_and_next_text(&mut tts_config, slides, slide);
+    }
+    let pronunciations = &config.pronunciations;
+    let language = match slide.config.verbalize {
+        Some(true) => Language::from_code(tts_config.language_code.as_deref()),
+        _ => None,
+    };
+    let max_chars = config.max_chars.unwrap_or(MAX_CHARS);
+    let mut pieces: Vec<(TTSConfig, Markup, BTreeMap<String, String>)> = Vec::new();
+    if slide.is_ssml() {
+        let (note, used) = pronounce_ssml(&slide.speaker_note, pronunciations)?;
+        let text = strip_ssml(&note);
+        if provider.supports_ssml() {
+            // SSML written by the user can't be split without breaking the tags.
+            let other = tts_config.other.as_mut().unwrap();
+            other.insert("input".to_string(), json!({ "ssml": note }));
+            pieces.push((tts_config, Markup::Text(text), used));
+        } else {
+            tracing::warn!(
+                "Slide {}: {provider} does not support SSML, so the text without tags is read",
+                slide.idx
+            );
+            for part in chunk_markup(vec![Markup::Text(text)], max_chars) {
+                pieces.push((tts_config.clone(), part, used.clone()));
+            }
+        }
+    } else {
+        for segment in slide.segments() {
+            let mut tts_config = tts_config.clone();
+            if let Some(speaker) = &segment.speaker {
+                tts_config.voice = Some(speaker_voice(config, slide, speaker));
+            }
+            let markup = parse_markup(&segment.text)?;
+            let (mut markup, used) = pronounce_markup(markup, pronunciations);
+            // Verbalized after the lexicon, so that its entries can contain
+            // numbers and symbols.
+            if let Some(language) = language {
+                markup = verbalize_markup(markup, language);
+            }
+            if !(provider.supports_ssml() && config.ssml_emphasis == Some(true)) {
+                markup = without_emphasis(markup);
+            }
+            let use_ssml = provider.supports_ssml() && has_markup(&markup);
+            let markup = chunk_markup(markup, max_chars);
+            if !use_ssml {
+                for part in markup {
+                    pieces.push((tts_config.clone(), part, used.clone()));
+                }
+                continue;
+            }
+            for group in group_markup(markup, max_chars) {
+                if group.iter().all(|part| matches!(part, Markup::Pause(_))) {
+                    for part in group {
+                        pieces.push((tts_config.clone(), part, BTreeMap::new()));
+                    }
+                    continue;
+                }
+                let mut tts_config = tts_config.clone();
+                let other = tts_config.other.as_mut().unwrap();
+                // Replaces the text input in the request body.
+                other.insert("input".to_string(), json!({ "ssml": ssml(&group) }));
+                pieces.push((tts_config, Markup::Text(plain_text(&group)), used.clone()));
+            }
+        }
+    }
+    let n = pieces.len();
+    let parts = pieces
+        .into_iter()
+        .enumerate()
+        .map(|(i, (mut tts_config, part, pronunciations))| {
+            let audio_path = if n == 1 {
+                audio_path(dir, slide, audio_ext)
+            } else {
+                audio_segment_path(dir, slide, i + 1, audio_ext)
+            };
+            let text = match part {
+                Markup::Text(text) | Markup::Emphasis(text) => text,
+                Markup::Slow(text) => {
+                    slow_down(&mut tts_config, provider, slide);
+                    text
+                }
+                Markup::Pause(duration) => {
+                    return AudioPart::Silence {
+                        duration,
+                        audio_path,
+                    }
+                }
+            };
+            AudioPart::Speech(AudioRequest {
+                slide,
+                text,
+                audio_path,
+                config: tts_config,
+                pronunciations,
+            })
+        })
+        .collect();
+    Ok(parts)
+}
+
+#[test]
+fn test_audio_parts_ssml() {
+    let config = Config {
+        voice: "en-US-Standard-A".to_string(),
+        ..Default::default()
+    };
+    let slide = Slide {
+        idx: 1,
+        speaker_note: "<speak>Foo <break time=\"1s\"/> bar.</speak>".to_string(),
+        config: crate::slide::SlideConfig {
+            ssml: Some(true),
+            ..Default::default()
+        },
+        speakers: Vec::new(),
+    };
+    let slides = vec![slide.clone()];
+    let provider = TtsProvider::Api(Provider::Google);
+    let parts = audio_parts(&provider, "_out", &slide, &slides, &config, "mp3").unwrap();
+    let AudioPart::Speech(request) = &parts[0] else {
+        panic!("expected speech");
+    };
+    assert_eq!(parts.len(), 1);
+    assert_eq!(request.text, "Foo bar.");
+    let other = request.config.other.as_ref().unwrap();
+    assert_eq!(other["input"], json!({ "ssml": slide.speaker_note }));
+}
+
+#[test]
+fn test_audio_parts_ssml_chunks() {
+    let config = Config {
+        voice: "en-US-Standard-A".to_string(),
+        max_chars: Some(20),
+        ..Default::default()
+    };
+    let slide = Slide {
+        idx: 1,
+        speaker_note: "One two. Three four. [pause 1s] Five six seven.".to_string(),
+        config: Default::default(),
+        speakers: Vec::new(),
+    };
+    let slides = vec![slide.clone()];
+    let provider = TtsProvider::Api(Provider::Google);
+    let parts = audio_parts(&provider, "_out", &slide, &slides, &config, "mp3").unwrap();
+    let inputs = parts
+        .iter()
+        .map(|part| match part {
+            AudioPart::Speech(request) => request.config.other.as_ref().unwrap()["input"].clone(),
+            _ => panic!("expected speech"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        inputs,
+        vec![
+            json!({ "ssml": "<speak>One two. Three four.</speak>" }),
+            json!({ "ssml": "<speak><break time=\"1000ms\"/> Five six seven.</speak>" }),
+        ]
+    );
+}
+
+#[test]
+fn test_audio_parts_pronunciations() {
+    let config = Config {
+        voice: "am_adam".to_string(),
+        pronunciations: HashMap::from([
+            ("trv".to_string(), "T R V".to_string()),
+            ("Typst".to_string(), "Tipst".to_string()),
+        ]),
+        ..Default::default()
+    };
+    let slide = Slide {
+        idx: 1,
+        speaker_note: "Run trv.".to_string(),
+        config: Default::default(),
+        speakers: Vec::new(),
+    };
+    let slides = vec![slide.clone()];
+    let provider = TtsProvider::Api(Provider::DeepInfra);
+    let parts = audio_parts(&provider, "_out", &slide, &slides, &config, "mp3").unwrap();
+    let AudioPart::Speech(request) = &parts[0] else {
+        panic!("expected speech");
+    };
+    assert_eq!(request.text, "Run T R V.");
+    let key = request.cache_key(&provider, &None);
+    assert_eq!(
+        key.pronunciations,
+        BTreeMap::from([("trv".to_string(), "T R V".to_string())])
+    );
+}
+
+#[test]
+fn test_audio_parts_verbalize() {
+    let config = Config {
+        voice: "am_adam".to_string(),
+        pronunciations: HashMap::from([("C++".to_string(), "C plus plus".to_string())]),
+        ..Default::default()
+    };
+    let slide = Slide {
+        idx: 1,
+        speaker_note: "In C++, x^2 + 1 [pause 1s] is 50 km.".to_string(),
+        config: crate::slide::SlideConfig {
+            verbalize: Some(true),
+            ..Default::default()
+        },
+        speakers: Vec::new(),
+    };
+    let slides = vec![slide.clone()];
+    let provider = TtsProvider::Api(Provider::DeepInfra);
+    let parts = audio_parts(&provider, "_out", &slide, &slides, &config, "mp3").unwrap();
+    let texts = parts
+        .iter()
+        .filter_map(|part| match part {
+            AudioPart::Speech(request) => Some(request.text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<&str>>();
+    assert_eq!(
+        texts,
+        vec!["In C plus plus, x squared plus one", "is fifty kilometers."]
+    );
+}
+
+/// Cache keys for all audio of the slides.
+///
+/// Returns the index of the slide together with the key.
+pub(crate) fn cache_keys(
+    provider: &TtsProvider,
+    slides: &[Slide],
+    config: &Config,
+) -> Vec<(usize, AudioCacheKey)> {
+    let audio_ext = audio_format(config);
+    slides
+        .iter()
+        .flat_map(|slide| {
+            let parts = match audio_parts(provider, "", slide, slides, config, &audio_ext) {
+                Ok(parts) => parts,
+                Err(e) => {
+                    tracing::error!("Slide {}: {e}", slide.idx);
+                    std::process::exit(1);
+                }
+            };
+            parts
+                .iter()
+                .filter_map(|part| match part {
+                    AudioPart::Speech(request) => {
+                        Some((slide.idx, request.cache_key(provider, &config.model)))
+                    }
+                    AudioPart::Silence { .. } | AudioPart::Recording { .. } => None,
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn generate_slide_audio(
+    provider: &TtsProvider,
+    keys: &Keys,
+    dir: &str,
+    slide: &Slide,
+    slides: &[Slide],
+    cache_dir: Option<&Path>,
+    config: &Config,
+    audio_ext: &str,
+) {
+    let idx = slide.idx;
+    tracing::info!("Slide {idx}: Generating audio file...");
+    let model = &config.model;
+    let max_attempts = config.max_attempts.unwrap_or(5);
+    let parts = match audio_parts(provider, dir, slide, slides, config, audio_ext) {
+        Ok(parts) => parts,
+        Err(e) => {
+            tracing::error!("Slide {}: {e}", slide.idx);
+            std::process::exit(1);
+        }
+    };
+    for part in &parts {
+        match part {
+            AudioPart::Speech(request) => {
+                generate_audio_file(provider, keys, request, cache_dir, model, max_attempts).await
+            }
+            AudioPart::Silence {
+                duration,
+                audio_path,
+            } => {
+                let parent = audio_path.parent().unwrap();
+                tokio::fs::create_dir_all(parent).await.unwrap();
+                let seconds = duration.num_milliseconds() as f64 / 1000.0;
+                tracing::info!("Slide {idx}: Generating {seconds}s of silence");
+                if let Err(e) = generate_silence(seconds, audio_path).await {
+                    tracing::error!("Slide {idx}: {e}");
+                    std::process::exit(1);
+                }
+            }
+            AudioPart::Recording { source, audio_path } => {
+                let parent = audio_path.parent().unwrap();
+                tokio::fs::create_dir_all(parent).await.unwrap();
+                tracing::info!("Slide {idx}: Using recording {}", source.display());
+                if let Err(e) = convert_recording(source, audio_path).await {
+                    tracing::error!("Slide {idx}: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    if 1 < parts.len() {
+        // Each part is generated (and cached) separately and then
+        // concatenated into the audio file for the slide.
+        let part_paths = parts
+            .iter()
+            .map(|part| part.audio_path().to_path_buf())
+            .collect::<Vec<PathBuf>>();
+        concat_audio(&part_paths, &audio_path(dir, slide, audio_ext)).await;
+    }
+}
+
+/// Generate the audio files for all slides.
+///
+/// Up to `jobs` slides are generated concurrently. The previous and next text
+/// for ElevenLabs is taken from the speaker notes, so it does not depend on
+/// the order in which the slides are generated. Caching is disabled when
+/// `cache_dir` is `None`.
+pub(crate) async fn generate_audio_files(
+    provider: &TtsProvider,
     dir: &str,
     slides: &Vec<Slide>,
-    cache: bool,
+    cache_dir: Option<&Path>,
     config: &Config,
     audio_ext: &str,
+    jobs: usize,
 ) {
     // Not using the keys from file (TODO: transformrs should support loading
     // keys from environment variables).
     let keys = transformrs::load_keys("not_used.env");
-    let mut tts_config = tts_config(config, provider);
-    let model = &config.model;
-    for slide in slides {
-        let idx = slide.idx;
-        tracing::info!("Slide {idx}: Generating audio file...");
-        if provider == &Provider::ElevenLabs {
-            set_previous_and_next_text(&mut tts_config, slides, idx);
-        }
-        generate_audio_file(
-            provider,
-            &keys,
-            dir,
-            slide,
-            cache,
-            &tts_config,
-            model,
-            audio_ext,
-        )
+    stream::iter(slides)
+        .map(|slide| {
+            generate_slide_audio(
+                provider, &keys, dir, slide, slides, cache_dir, config, audio_ext,
+            )
+        })
+        .buffer_unordered(jobs.max(1))
+        .collect::<Vec<()>>()
         .await;
-    }
 }
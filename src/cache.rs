@@ -0,0 +1,249 @@
+use crate::audio::cache_keys;
+use crate::audio::AudioCacheKey;
+use crate::parse_config;
+use crate::slide;
+use crate::tts_provider;
+use crate::CacheArgs;
+use crate::CachePruneArgs;
+use crate::CacheTask;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// An entry in the audio cache.
+struct Entry {
+    hash: String,
+    key: Option<AudioCacheKey>,
+    /// Paths of all files that belong to the entry.
+    paths: Vec<PathBuf>,
+    /// Size of the files in bytes.
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Hash of the entry that the file in the cache belongs to.
+///
+/// Files are named `<hash>.<ext>` or `<hash>.cache_key`.
+fn file_hash(path: &Path) -> String {
+    let filename = path.file_name().unwrap().to_str().unwrap();
+    filename.split('.').next().unwrap().to_string()
+}
+
+fn entries(cache_dir: &Path) -> Vec<Entry> {
+    let audio_dir = cache_dir.join("audio");
+    if !audio_dir.exists() {
+        return Vec::new();
+    }
+    let mut entries: HashMap<String, Entry> = HashMap::new();
+    for file in std::fs::read_dir(audio_dir).unwrap() {
+        let path = file.unwrap().path();
+        if !path.is_file() {
+            continue;
+        }
+        let hash = file_hash(&path);
+        let metadata = std::fs::metadata(&path).unwrap();
+        let entry = entries.entry(hash.clone()).or_insert(Entry {
+            hash,
+            key: None,
+            paths: Vec::new(),
+            size: 0,
+            modified: SystemTime::UNIX_EPOCH,
+        });
+        if path.extension().unwrap_or_default() == "cache_key" {
+            let key = std::fs::read_to_string(&path).unwrap();
+            match serde_json::from_str::<AudioCacheKey>(&key) {
+                Ok(key) => entry.key = Some(key),
+                Err(e) => tracing::warn!("Failed to parse {}: {e}", path.display()),
+            }
+        } else {
+            entry.size += metadata.len();
+        }
+        entry.modified = entry.modified.max(metadata.modified().unwrap());
+        entry.paths.push(path);
+    }
+    let mut entries = entries.into_values().collect::<Vec<Entry>>();
+    entries.sort_by_key(|entry| entry.modified);
+    entries
+}
+
+/// Slides that use each cache entry in the presentation.
+fn referenced(input: &PathBuf) -> HashMap<String, Vec<usize>> {
+    let config = parse_config(input);
+    let provider = tts_provider(&config);
+    let slides = slide::slides(input.to_str().unwrap(), &config);
+    let mut referenced: HashMap<String, Vec<usize>> = HashMap::new();
+    for (idx, key) in cache_keys(&provider, &slides, &config) {
+        referenced.entry(key.hash()).or_default().push(idx);
+    }
+    referenced
+}
+
+fn format_size(bytes: u64) -> String {
+    let bytes = bytes as f64;
+    if bytes < 1000.0 {
+        format!("{bytes} B")
+    } else if bytes < 1_000_000.0 {
+        format!("{:.1} kB", bytes / 1000.0)
+    } else {
+        format!("{:.1} MB", bytes / 1_000_000.0)
+    }
+}
+
+#[test]
+fn test_format_size() {
+    assert_eq!(format_size(999), "999 B");
+    assert_eq!(format_size(12_345), "12.3 kB");
+    assert_eq!(format_size(4_500_000), "4.5 MB");
+}
+
+fn format_age(modified: SystemTime) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default()
+        .as_secs();
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+/// First characters of the text on a single line.
+fn preview(text: &str, len: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if text.chars().count() <= len {
+        text
+    } else {
+        let truncated = text.chars().take(len - 1).collect::<String>();
+        format!("{truncated}…")
+    }
+}
+
+#[test]
+fn test_preview() {
+    assert_eq!(preview("foo\n\nbar", 10), "foo bar");
+    assert_eq!(preview("foo bar baz", 6), "foo b…");
+}
+
+fn list(cache_dir: &Path, input: &Option<PathBuf>) {
+    let referenced = match input {
+        Some(input) => referenced(input),
+        None => HashMap::new(),
+    };
+    println!(
+        "{:<8} {:<40} {:<16} {:<20} {:>9} {:>5}",
+        "SLIDE", "TEXT", "PROVIDER", "VOICE", "SIZE", "AGE"
+    );
+    let entries = entries(cache_dir);
+    for entry in &entries {
+        let slides = match referenced.get(&entry.hash) {
+            Some(idxs) => idxs
+                .iter()
+                .map(|idx| idx.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+            None => "-".to_string(),
+        };
+        let (text, provider, voice) = match &entry.key {
+            Some(key) => (
+                preview(&key.text, 40),
+                preview(&key.provider, 16),
+                preview(&key.config.voice.clone().unwrap_or_default(), 20),
+            ),
+            None => (
+                "<missing cache key>".to_string(),
+                "".to_string(),
+                "".to_string(),
+            ),
+        };
+        println!(
+            "{:<8} {:<40} {:<16} {:<20} {:>9} {:>5}",
+            slides,
+            text,
+            provider,
+            voice,
+            format_size(entry.size),
+            format_age(entry.modified)
+        );
+    }
+    let total = entries.iter().map(|entry| entry.size).sum::<u64>();
+    println!(
+        "\n{} entries ({}) in {}",
+        entries.len(),
+        format_size(total),
+        cache_dir.display()
+    );
+}
+
+fn remove(entry: &Entry) {
+    for path in &entry.paths {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+/// Ask the user whether to continue.
+fn confirm(question: &str) -> bool {
+    print!("{question} [y/N] ");
+    std::io::stdout().flush().unwrap();
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).unwrap();
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+fn prune(cache_dir: &Path, args: &CachePruneArgs) {
+    let mut referenced = HashSet::new();
+    for input in &args.inputs {
+        referenced.extend(self::referenced(input).into_keys());
+    }
+    let entries = entries(cache_dir);
+    let unreferenced = entries
+        .iter()
+        .filter(|entry| !referenced.contains(&entry.hash))
+        .collect::<Vec<&Entry>>();
+    if unreferenced.is_empty() {
+        tracing::info!("No entries to remove");
+        return;
+    }
+    let freed = unreferenced.iter().map(|entry| entry.size).sum::<u64>();
+    let question = format!(
+        "Remove {} entries ({}) from {}? This includes the audio of all \
+        presentations that were not given.",
+        unreferenced.len(),
+        format_size(freed),
+        cache_dir.display()
+    );
+    if !args.yes && !confirm(&question) {
+        tracing::info!("Aborted");
+        return;
+    }
+    for entry in &unreferenced {
+        remove(entry);
+    }
+    let kept = entries.len() - unreferenced.len();
+    tracing::info!(
+        "Removed {} entries ({}) and kept {kept} entries",
+        unreferenced.len(),
+        format_size(freed)
+    );
+}
+
+fn clear(cache_dir: &Path) {
+    let entries = entries(cache_dir);
+    for entry in &entries {
+        remove(entry);
+    }
+    let freed = entries.iter().map(|entry| entry.size).sum::<u64>();
+    tracing::info!("Removed {} entries ({})", entries.len(), format_size(freed));
+}
+
+pub(crate) fn cache(cache_args: &CacheArgs, cache_dir: &Path) {
+    match &cache_args.task {
+        CacheTask::List(args) => list(cache_dir, &args.input),
+        CacheTask::Prune(args) => prune(cache_dir, args),
+        CacheTask::Clear => clear(cache_dir),
+    }
+}
@@ -0,0 +1,186 @@
+use crate::slide::Slide;
+use crate::subtitles::slide_cues;
+use crate::video::SlideDuration;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+
+/// Vertical position of the captions.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CaptionPosition {
+    Top,
+    Center,
+    #[default]
+    Bottom,
+}
+
+/// Style of the captions that are burned into the video.
+///
+/// Set via the `[captions]` table in the trv config. For example:
+///
+/// ```toml
+/// [captions]
+/// font = "DejaVu Sans"
+/// font_size = 64
+/// position = "bottom"
+/// background = "black@0.6"
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CaptionsConfig {
+    /// Font family.
+    ///
+    /// This is looked up via fontconfig. If not set, ffmpeg picks a default.
+    pub font: Option<String>,
+
+    /// Font size in pixels.
+    ///
+    /// The video has a height of 1920 pixels.
+    pub font_size: u32,
+
+    /// Font color.
+    ///
+    /// Any color that ffmpeg accepts, for example `white` or `#FFFFFF`.
+    pub font_color: String,
+
+    /// Vertical position of the captions.
+    pub position: CaptionPosition,
+
+    /// Color of the box behind the captions.
+    ///
+    /// For example, `black@0.6` for a semi-transparent black box. Set to an
+    /// empty string to disable the box.
+    pub background: String,
+
+    /// Distance in pixels between the captions and the edge of the video.
+    pub margin: u32,
+
+    /// Maximum number of characters per line.
+    ///
+    /// Longer captions are wrapped over multiple lines.
+    pub line_length: usize,
+}
+
+impl Default for CaptionsConfig {
+    fn default() -> Self {
+        Self {
+            font: None,
+            font_size: 64,
+            font_color: "white".to_string(),
+            position: CaptionPosition::Bottom,
+            background: "black@0.6".to_string(),
+            margin: 80,
+            line_length: 42,
+        }
+    }
+}
+
+/// Wrap the text at word boundaries into lines of at most `line_length` characters.
+///
+/// Words that are longer than `line_length` are placed on their own line.
+fn wrap(text: &str, line_length: usize) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let len = line.chars().count() + 1 + word.chars().count();
+        if !line.is_empty() && line_length < len {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+#[test]
+fn test_wrap() {
+    assert_eq!(wrap("foo bar baz", 7), "foo bar\nbaz");
+    assert_eq!(wrap("foo  bar", 80), "foo bar");
+    assert_eq!(wrap("foobarbaz qux", 3), "foobarbaz\nqux");
+}
+
+/// Escape a value for use as a filter option in an ffmpeg filter graph.
+///
+/// Values are parsed twice by ffmpeg: once as part of the filter graph and
+/// once as a filter option. See
+/// https://ffmpeg.org/ffmpeg-filters.html#Notes-on-filtergraph-escaping.
+fn escape_filter_value(value: &str) -> String {
+    let option = value
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace(':', "\\:");
+    format!("'{}'", option.replace('\'', "'\\''"))
+}
+
+#[test]
+fn test_escape_filter_value() {
+    assert_eq!(escape_filter_value("_out/1.txt"), "'_out/1.txt'");
+    assert_eq!(escape_filter_value("C:/a"), "'C\\:/a'");
+    assert_eq!(escape_filter_value("it's"), "'it\\'\\''s'");
+}
+
+fn caption_path(dir: &str, slide: &Slide, cue: usize) -> std::path::PathBuf {
+    let idx = slide.idx;
+    let filename = format!("{idx}.{cue}.txt");
+    Path::new(dir).join("captions").join(filename)
+}
+
+/// Filters that draw the captions for the slide on top of the slide image.
+///
+/// The text of each caption is written to a file, which avoids having to
+/// escape the text inside the filter graph. Returns filters that can be
+/// appended to the filter chain of the slide, for example,
+/// `,drawtext=...,drawtext=...`.
+pub(crate) fn caption_filters(
+    dir: &str,
+    slide: &Slide,
+    duration: &SlideDuration,
+    config: &CaptionsConfig,
+) -> String {
+    let captions_dir = Path::new(dir).join("captions");
+    if !captions_dir.exists() {
+        std::fs::create_dir_all(&captions_dir).unwrap();
+    }
+    let y = match config.position {
+        CaptionPosition::Top => format!("{}", config.margin),
+        CaptionPosition::Center => "(h-text_h)/2".to_string(),
+        CaptionPosition::Bottom => format!("h-text_h-{}", config.margin),
+    };
+    let mut filters = String::new();
+    for (i, cue) in slide_cues(slide, duration).iter().enumerate() {
+        let path = caption_path(dir, slide, i + 1);
+        std::fs::write(&path, wrap(&cue.text, config.line_length)).unwrap();
+        // The audio starts after the transition into the slide.
+        let start = (duration.transition + cue.start).num_milliseconds() as f64 / 1000.0;
+        let end = (duration.transition + cue.end).num_milliseconds() as f64 / 1000.0;
+        let mut options = vec![
+            format!("textfile={}", escape_filter_value(path.to_str().unwrap())),
+            "expansion=none".to_string(),
+            format!("fontsize={}", config.font_size),
+            format!("fontcolor={}", escape_filter_value(&config.font_color)),
+            "line_spacing=10".to_string(),
+            "x=(w-text_w)/2".to_string(),
+            format!("y={y}"),
+            format!("enable='between(t,{start},{end})'"),
+        ];
+        if let Some(font) = &config.font {
+            options.push(format!("font={}", escape_filter_value(font)));
+        }
+        if !config.background.is_empty() {
+            options.push("box=1".to_string());
+            options.push(format!(
+                "boxcolor={}",
+                escape_filter_value(&config.background)
+            ));
+            options.push("boxborderw=20".to_string());
+        }
+        filters.push_str(&format!(",drawtext={}", options.join(":")));
+    }
+    filters
+}
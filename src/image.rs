@@ -1,19 +1,77 @@
-use crate::path::PathStr;
 use std::path::Path;
-use std::path::PathBuf;
 
-pub fn generate_images(input: &PathBuf, dir: &str) {
-    let image_dir = Path::new(dir).join("image");
-    if !image_dir.exists() {
-        std::fs::create_dir_all(&image_dir).unwrap();
+/// Move the rendered pages into the image directory.
+///
+/// Pages that are identical to the existing images are dropped, so that the
+/// existing images keep their modification time. Images of pages that no
+/// longer exist are removed. Returns the numbers of the pages that changed.
+fn update_images(render_dir: &Path, image_dir: &Path) -> Vec<usize> {
+    let mut pages = Vec::new();
+    for file in std::fs::read_dir(render_dir).unwrap() {
+        let path = file.unwrap().path();
+        let page = path.file_stem().unwrap().to_str().unwrap();
+        pages.push(page.parse::<usize>().unwrap());
     }
-    let image_dir = image_dir.to_string();
+    pages.sort();
+    let mut changed = Vec::new();
+    for page in &pages {
+        let filename = format!("{page}.png");
+        let rendered = render_dir.join(&filename);
+        let existing = image_dir.join(&filename);
+        if existing.exists()
+            && std::fs::read(&existing).unwrap() == std::fs::read(&rendered).unwrap()
+        {
+            std::fs::remove_file(&rendered).unwrap();
+        } else {
+            std::fs::rename(&rendered, &existing).unwrap();
+            changed.push(*page);
+        }
+    }
+    for file in std::fs::read_dir(image_dir).unwrap() {
+        let path = file.unwrap().path();
+        let page = path.file_stem().unwrap().to_str().unwrap().parse::<usize>();
+        if path.is_file() && page.is_ok_and(|page| !pages.contains(&page)) {
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+    changed
+}
+
+#[test]
+fn test_update_images() {
+    let dir = tempfile::tempdir().unwrap();
+    let render_dir = dir.path().join("render");
+    let image_dir = dir.path().join("image");
+    std::fs::create_dir_all(&render_dir).unwrap();
+    std::fs::create_dir_all(&image_dir).unwrap();
+    for (page, content) in [(1, "a"), (2, "b"), (3, "c")] {
+        std::fs::write(image_dir.join(format!("{page}.png")), content).unwrap();
+    }
+    for (page, content) in [(1, "a"), (2, "x")] {
+        std::fs::write(render_dir.join(format!("{page}.png")), content).unwrap();
+    }
+    assert_eq!(update_images(&render_dir, &image_dir), vec![2]);
+    assert_eq!(
+        std::fs::read_to_string(image_dir.join("2.png")).unwrap(),
+        "x"
+    );
+    assert!(!image_dir.join("3.png").exists());
+    assert_eq!(std::fs::read_dir(&render_dir).unwrap().count(), 0);
+}
+
+#[cfg(feature = "embed-typst")]
+fn render_pages(input: &Path, render_dir: &Path) {
+    crate::world::render_pages(input, render_dir);
+}
+
+#[cfg(not(feature = "embed-typst"))]
+fn render_pages(input: &Path, render_dir: &Path) {
     let output = std::process::Command::new("typst")
         .arg("compile")
         .arg("--format=png")
         .arg("--ppi=300")
         .arg(input)
-        .arg(format!("{image_dir}/{{p}}.png"))
+        .arg(render_dir.join("{p}.png"))
         .output()
         .expect("Failed to run typst compile");
 
@@ -27,3 +85,29 @@ pub fn generate_images(input: &PathBuf, dir: &str) {
         tracing::info!("{}", String::from_utf8_lossy(&output.stdout));
     }
 }
+
+/// Render the pages of the presentation to images.
+///
+/// Typst always renders all pages, so the pages are rendered into a separate
+/// directory first. Only the images of pages that changed are replaced, which
+/// allows the video segments and the watch page to skip unchanged slides.
+pub fn generate_images(input: &Path, dir: &str) {
+    let image_dir = Path::new(dir).join("image");
+    if !image_dir.exists() {
+        std::fs::create_dir_all(&image_dir).unwrap();
+    }
+    let render_dir = Path::new(dir).join("render");
+    if render_dir.exists() {
+        std::fs::remove_dir_all(&render_dir).unwrap();
+    }
+    std::fs::create_dir_all(&render_dir).unwrap();
+    render_pages(input, &render_dir);
+
+    let changed = update_images(&render_dir, &image_dir);
+    std::fs::remove_dir(&render_dir).unwrap();
+    if changed.is_empty() {
+        tracing::info!("No slide images changed");
+    } else {
+        tracing::info!("Updated images of slides {changed:?}");
+    }
+}
@@ -1,13 +1,28 @@
 mod audio;
+mod cache;
+mod captions;
 mod image;
+mod markdown;
+mod markup;
 mod path;
+mod pdfpc;
+mod pronunciation;
+mod provider;
 mod slide;
+mod subtitles;
+mod verbalize;
 mod video;
 mod watch;
+#[cfg(feature = "embed-typst")]
+mod world;
 
+use crate::captions::CaptionsConfig;
+use crate::provider::TtsProvider;
 use crate::slide::Slide;
+use crate::video::Transition;
 use clap::Parser;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -21,8 +36,24 @@ pub(crate) struct Config {
     ///
     /// Can be used to pass for example
     /// `--provider=openai-compatible(kokoros.transformrs.org)`.
+    ///
+    /// Set to `command` to run a local program instead (see `command`).
     pub provider: Option<String>,
 
+    /// Command that generates the audio for a slide.
+    ///
+    /// Used when `provider = "command"`. The command is run via bash and gets
+    /// the speaker note on stdin. The placeholder `{out}` is replaced by the
+    /// path to which the command should write the audio. Other supported
+    /// placeholders are `{voice}`, `{speed}`, and `{language_code}`. For
+    /// example:
+    ///
+    /// ```toml
+    /// provider = "command"
+    /// command = "piper --model {voice} --output_file {out}"
+    /// ```
+    pub command: Option<String>,
+
     /// Text-to-speech model.
     ///
     /// For the OpenAI compatible API from Kokoros, use `tts-1`.
@@ -31,10 +62,45 @@ pub(crate) struct Config {
     /// Text-to-speech voice.
     ///
     /// Note that DeepInfra at the time of writing supports more voices that
-    /// Kokoros. If Kokoros respond with an empty file (which ffmpeg then
-    /// crashes on), try a different voice.
+    /// Kokoros. Kokoros sometimes responds with an empty file. Such responses
+    /// are rejected and retried, but if it keeps happening, try a different
+    /// voice.
     pub voice: String,
 
+    /// Voices per speaker.
+    ///
+    /// Speaker notes can contain speaker tags such as `[host]:` and
+    /// `[guest]:`. Each part of the note is then read by the voice of the
+    /// speaker. For example:
+    ///
+    /// ```toml
+    /// [voices]
+    /// host = "am_adam"
+    /// guest = "af_bella"
+    /// ```
+    #[serde(default)]
+    pub voices: HashMap<String, String>,
+
+    /// Spoken forms of words that the voice mispronounces.
+    ///
+    /// The written forms are replaced in the speaker notes before they are
+    /// sent to the provider. Subtitles and `trv notes` keep the written
+    /// forms. For example:
+    ///
+    /// ```toml
+    /// [pronunciations]
+    /// trv = "T R V"
+    /// Typst = "Tipst"
+    /// ```
+    #[serde(default)]
+    pub pronunciations: HashMap<String, String>,
+
+    /// File with more pronunciations, relative to the input file.
+    ///
+    /// The file contains `written = "spoken"` lines in TOML. Entries in the
+    /// `[pronunciations]` table take precedence.
+    pub pronunciations_file: Option<PathBuf>,
+
     /// Audio format.
     ///
     /// This setting usually should not be necessary since ffmpeg can handle
@@ -54,6 +120,70 @@ pub(crate) struct Config {
     ///
     /// This setting is required by Google.
     pub language_code: Option<String>,
+
+    /// Transition between slides.
+    ///
+    /// One of `none`, `fade` (alias `crossfade`), `slide-left`, or
+    /// `fade-through-black`. Can be overridden per slide via the front matter
+    /// of the speaker note.
+    pub transition: Option<Transition>,
+
+    /// Duration of the transitions in seconds.
+    ///
+    /// Defaults to 0.5 seconds.
+    pub transition_duration: Option<f64>,
+
+    /// Number of slides for which audio is generated concurrently.
+    ///
+    /// Can be overridden via `--jobs`. Defaults to 1.
+    pub concurrency: Option<usize>,
+
+    /// Maximum number of attempts per text-to-speech request.
+    ///
+    /// Requests that fail due to rate limits, server or network errors, or
+    /// invalid audio are retried with an exponential backoff. Other errors,
+    /// such as an invalid key, are not retried. Defaults to 5.
+    pub max_attempts: Option<u32>,
+
+    /// Maximum number of characters per text-to-speech request.
+    ///
+    /// Longer speaker notes are split at paragraphs and sentences. The parts
+    /// are generated and cached separately and then joined with a short pause
+    /// at paragraph boundaries. Defaults to 2000.
+    pub max_chars: Option<usize>,
+
+    /// Whether the speaker notes are SSML.
+    ///
+    /// The notes are then validated and sent as-is to providers that support
+    /// SSML, such as Google. Can be overridden per slide via the front
+    /// matter of the speaker note.
+    pub ssml: Option<bool>,
+
+    /// Whether the speaker notes are Markdown.
+    ///
+    /// Markdown notes are rendered to text that can be read out loud. For
+    /// example, `*emphasis*` and `` `code` `` are read without the markers,
+    /// links are read as their text, and list items are read as sentences.
+    /// Defaults to `false`. Notes in SSML are never read as Markdown.
+    pub markdown: Option<bool>,
+
+    /// Write out numbers, units, dates, and math in words.
+    ///
+    /// For example, `x^2 + 1` is read as "x squared plus one". Uses the
+    /// `language_code`, which is English by default. Supported are English
+    /// and German. Can be turned off per slide via `verbalize = false` in the
+    /// front matter of the speaker note. Defaults to `false`.
+    pub verbalize: Option<bool>,
+
+    /// Read Markdown emphasis with SSML `<emphasis>`.
+    ///
+    /// Only used for providers that support SSML, such as Google. Defaults to
+    /// `false`, since not all voices support emphasis.
+    pub ssml_emphasis: Option<bool>,
+
+    /// Style of the captions for `trv build --burn-captions`.
+    #[serde(default)]
+    pub captions: CaptionsConfig,
 }
 
 /// Parse the config from the Typst input file.
@@ -82,7 +212,17 @@ fn parse_config(input: &PathBuf) -> Config {
     if config_str.is_empty() {
         return Config::default();
     }
-    let config: Config = toml::from_str(&config_str).unwrap();
+    let mut config: Config = toml::from_str(&config_str).unwrap();
+    if let Some(file) = &config.pronunciations_file {
+        let path = input.parent().unwrap().join(file);
+        let content = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {e}", path.display()));
+        let pronunciations: HashMap<String, String> = toml::from_str(&content)
+            .unwrap_or_else(|e| panic!("Failed to parse {}: {e}", path.display()));
+        for (written, spoken) in pronunciations {
+            config.pronunciations.entry(written).or_insert(spoken);
+        }
+    }
     config
 }
 
@@ -109,6 +249,14 @@ pub(crate) struct BuildArgs {
     /// `libfdk_aac`.
     #[arg(long, default_value = "opus")]
     audio_codec: String,
+
+    /// Burn the speaker notes as captions into the video.
+    ///
+    /// This is useful for videos that are watched without sound, such as on
+    /// social media. The style of the captions can be set via the
+    /// `[captions]` table in the trv config.
+    #[arg(long)]
+    burn_captions: bool,
 }
 
 #[derive(Clone, Debug, Parser)]
@@ -131,6 +279,46 @@ pub(crate) struct NotesArgs {
     input: PathBuf,
 }
 
+#[derive(Clone, Debug, Parser)]
+pub(crate) struct CacheListArgs {
+    /// Path to the Typst input file.
+    ///
+    /// When set, shows which slides of the presentation use each entry.
+    input: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Parser)]
+pub(crate) struct CachePruneArgs {
+    /// Paths to the Typst input files.
+    ///
+    /// The cache is shared between presentations, so entries are kept when
+    /// any of the given presentations uses them.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Remove the entries without asking for confirmation.
+    #[arg(long)]
+    yes: bool,
+}
+
+#[derive(Clone, Debug, clap::Subcommand)]
+pub(crate) enum CacheTask {
+    /// List the entries in the audio cache.
+    List(CacheListArgs),
+
+    /// Remove the entries that are not used by any of the presentations.
+    Prune(CachePruneArgs),
+
+    /// Remove all entries.
+    Clear,
+}
+
+#[derive(Clone, Debug, Parser)]
+pub(crate) struct CacheArgs {
+    #[command(subcommand)]
+    task: CacheTask,
+}
+
 #[derive(Clone, Debug, clap::Subcommand)]
 enum Task {
     /// Build the video.
@@ -143,6 +331,9 @@ enum Task {
     ///
     /// This can be useful to review the narration.
     Notes(NotesArgs),
+
+    /// Inspect and clean up the audio cache.
+    Cache(CacheArgs),
 }
 
 #[derive(Parser)]
@@ -162,6 +353,20 @@ pub(crate) struct Arguments {
     /// Enable caching.
     #[arg(long, default_value = "true")]
     cache: Option<bool>,
+
+    /// Cache directory.
+    ///
+    /// Defaults to `$XDG_CACHE_HOME/trv` or `~/.cache/trv`. The cache is
+    /// shared between presentations.
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+
+    /// Number of slides for which audio is generated concurrently.
+    ///
+    /// Overrides `concurrency` in the trv config. Be aware that providers may
+    /// rate limit requests when this is set too high.
+    #[arg(long)]
+    jobs: Option<usize>,
 }
 
 /// Initialize logging with the given level.
@@ -179,33 +384,71 @@ pub(crate) fn audio_format(config: &Config) -> String {
     config.audio_format.clone().unwrap_or("mp3".to_string())
 }
 
+/// Text-to-speech provider from the config.
+///
+/// Defaults to DeepInfra.
+pub(crate) fn tts_provider(config: &Config) -> TtsProvider {
+    let provider = config
+        .provider
+        .as_ref()
+        .map(|p| TtsProvider::from_str(p).unwrap());
+    provider.unwrap_or(TtsProvider::Api(Provider::DeepInfra))
+}
+
+fn cache_dir(args: &Arguments) -> PathBuf {
+    args.cache_dir
+        .clone()
+        .unwrap_or_else(path::default_cache_dir)
+}
+
 pub(crate) async fn build(
     input: PathBuf,
     config: &Config,
     args: &Arguments,
     release: bool,
     audio_codec: Option<String>,
+    burn_captions: bool,
 ) -> Vec<Slide> {
     let out_dir = &args.out_dir;
 
-    let provider = config
-        .provider
-        .as_ref()
-        .map(|p| Provider::from_str(p).unwrap());
-    let provider = provider.unwrap_or(Provider::DeepInfra);
+    let provider = tts_provider(config);
 
-    let slides = slide::slides(input.to_str().unwrap());
+    let slides = slide::slides(input.to_str().unwrap(), config);
     if slides.is_empty() {
         panic!("No slides found in input file: {}", input.display());
     }
     image::generate_images(&input, out_dir);
     let audio_ext = audio_format(config);
-    let cache = args.cache.unwrap();
-    audio::generate_audio_files(&provider, out_dir, &slides, cache, config, &audio_ext).await;
+    let cache_dir = if args.cache.unwrap() {
+        Some(cache_dir(args))
+    } else {
+        None
+    };
+    let jobs = args.jobs.or(config.concurrency).unwrap_or(1);
+    audio::generate_audio_files(
+        &provider,
+        out_dir,
+        &slides,
+        cache_dir.as_deref(),
+        config,
+        &audio_ext,
+        jobs,
+    )
+    .await;
     let output = "out.mp4";
     if release {
         let audio_codec = audio_codec.unwrap();
-        video::combine_video(out_dir, &slides, config, &provider, output, &audio_codec);
+        let durations = video::slide_durations(out_dir, &slides, config, &provider);
+        video::combine_video(
+            out_dir,
+            &slides,
+            &durations,
+            config,
+            output,
+            &audio_codec,
+            burn_captions,
+        );
+        subtitles::write_subtitles(out_dir, &slides, &durations, output);
     }
     slides
 }
@@ -236,6 +479,7 @@ async fn main() {
                 &args,
                 release,
                 audio_codec,
+                build_args.burn_captions,
             )
             .await;
         }
@@ -245,14 +489,16 @@ async fn main() {
         }
         Task::Notes(ref notes_args) => {
             let input = notes_args.input.to_str().unwrap();
-            let slides = slide::slides(input);
+            let config = parse_config(&notes_args.input);
+            let slides = slide::slides(input, &config);
             for (i, slide) in slides.iter().enumerate() {
                 if i == 0 {
-                    println!("{}", slide.speaker_note);
+                    println!("{}", slide.plain_text());
                 } else {
-                    println!("\n{}", slide.speaker_note);
+                    println!("\n{}", slide.plain_text());
                 }
             }
         }
+        Task::Cache(ref cache_args) => cache::cache(cache_args, &cache_dir(&args)),
     };
 }
@@ -0,0 +1,152 @@
+use crate::slide::speaker_tag;
+use pulldown_cmark::Event;
+use pulldown_cmark::Parser;
+use pulldown_cmark::Tag;
+use pulldown_cmark::TagEnd;
+
+/// Remove the indentation that all lines have in common.
+///
+/// Speaker notes are usually indented inside the Typst raw block, which
+/// Markdown would otherwise read as a code block.
+fn dedent(text: &str) -> String {
+    let indent = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    text.lines()
+        .map(|line| line.get(indent..).unwrap_or("").trim_end())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Escape speaker tags such as `[host]:` at the start of a line.
+///
+/// Markdown would otherwise read them as link reference definitions.
+fn escape_speaker_tags(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let indent = line.len() - line.trim_start().len();
+            let rest = &line[indent..];
+            if speaker_tag(rest).is_some() {
+                format!("{}\\{}", &line[..indent], rest)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// End the sentence with a period if it doesn't end with punctuation.
+fn end_sentence(text: &mut String) {
+    let trimmed = text.trim_end().len();
+    text.truncate(trimmed);
+    let last = text
+        .trim_end_matches("[/emphasis]")
+        .trim_end_matches("[/slow]")
+        .chars()
+        .next_back();
+    if last.is_some_and(|c| c.is_alphanumeric()) {
+        text.push('.');
+    }
+}
+
+/// Render a Markdown speaker note to text that can be read out loud.
+///
+/// Markup such as `*emphasis*` and `` `code` `` is removed, links are
+/// replaced by their text, and list items are turned into sentences. HTML is
+/// kept as text, since notes such as "returns `Vec<String>`" are more often
+/// written without backticks than they contain real HTML.
+/// Emphasis is kept as `[emphasis]...[/emphasis]` markup, so that it can be
+/// sent as SSML to providers that support it.
+pub(crate) fn speakable(text: &str) -> String {
+    let text = escape_speaker_tags(&dedent(text));
+    let mut out = String::new();
+    let mut emphasis = 0;
+    let mut lists = 0;
+    for event in Parser::new(&text) {
+        match event {
+            Event::Text(text) | Event::Code(text) => out.push_str(&text),
+            Event::Html(text) | Event::InlineHtml(text) => out.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => out.push('\n'),
+            Event::Start(Tag::Emphasis | Tag::Strong) => {
+                if emphasis == 0 {
+                    out.push_str("[emphasis]");
+                }
+                emphasis += 1;
+            }
+            Event::End(TagEnd::Emphasis | TagEnd::Strong) => {
+                emphasis -= 1;
+                if emphasis == 0 {
+                    out.push_str("[/emphasis]");
+                }
+            }
+            Event::Start(Tag::List(_)) => {
+                if 0 < lists {
+                    end_sentence(&mut out);
+                    out.push(' ');
+                }
+                lists += 1;
+            }
+            Event::End(TagEnd::List(_)) => {
+                lists -= 1;
+                if lists == 0 {
+                    out.truncate(out.trim_end().len());
+                    out.push_str("\n\n");
+                }
+            }
+            Event::End(TagEnd::Item) => {
+                end_sentence(&mut out);
+                out.push(' ');
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                end_sentence(&mut out);
+                out.push_str("\n\n");
+            }
+            Event::End(
+                TagEnd::Paragraph | TagEnd::CodeBlock | TagEnd::BlockQuote(_) | TagEnd::HtmlBlock,
+            ) => {
+                if 0 < lists {
+                    out.push(' ');
+                } else {
+                    out.push_str("\n\n");
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+#[test]
+fn test_speakable() {
+    let text = "
+        # Intro
+
+        This is **very** important, see [the docs](https://example.com).
+        Run `trv build` [pause 1s] now.
+
+        - first point
+        - second point!
+
+        [host]: Bye.
+    ";
+    assert_eq!(
+        speakable(text).trim(),
+        "Intro.\n\n\
+         This is [emphasis]very[/emphasis] important, see the docs.\n\
+         Run trv build [pause 1s] now.\n\n\
+         first point. second point!\n\n\
+         [host]: Bye."
+    );
+    assert_eq!(
+        speakable("- a\n  - b\n- *c*").trim(),
+        "a. b. [emphasis]c[/emphasis]."
+    );
+    assert_eq!(
+        speakable("Returns a Vec<String> or <b>nothing</b>.").trim(),
+        "Returns a Vec<String> or <b>nothing</b>."
+    );
+}
@@ -0,0 +1,296 @@
+use crate::slide::parse_duration;
+
+/// A part of a speaker note after parsing the inline markup.
+///
+/// The markup controls the pacing of the narration:
+///
+/// ```md
+/// The answer is [pause 1s] 42. Or, [slow]very slowly[/slow], forty-two.
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Markup {
+    Text(String),
+    /// Text that is read slower, marked with `[slow]...[/slow]`.
+    Slow(String),
+    /// Silence, marked with, for example, `[pause 1s]`.
+    Pause(chrono::Duration),
+    /// Text that is stressed, marked with `[emphasis]...[/emphasis]`.
+    ///
+    /// Markdown emphasis in the speaker notes is translated to this markup.
+    Emphasis(String),
+}
+
+/// Speed of `[slow]` text relative to the normal speed.
+pub(crate) const SLOW_SPEED: f64 = 0.8;
+
+fn push_text(parts: &mut Vec<Markup>, text: &mut String) {
+    let trimmed = text.trim();
+    if !trimmed.is_empty() {
+        parts.push(Markup::Text(trimmed.to_string()));
+    }
+    text.clear();
+}
+
+/// Parse the inline markup in the text.
+///
+/// Brackets that are not part of the markup, such as speaker tags, are kept
+/// as text.
+pub(crate) fn parse_markup(text: &str) -> Result<Vec<Markup>, String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        current.push_str(&rest[..start]);
+        let tag = &rest[start..];
+        if let Some(after) = tag
+            .strip_prefix("[pause")
+            .filter(|after| after.starts_with([' ', ']']))
+        {
+            let end = after
+                .find(']')
+                .ok_or("[pause] without closing bracket".to_string())?;
+            let duration = match after[..end].trim() {
+                "" => chrono::Duration::seconds(1),
+                duration => parse_duration(duration).map_err(|e| format!("[pause]: {e}"))?,
+            };
+            push_text(&mut parts, &mut current);
+            parts.push(Markup::Pause(duration));
+            rest = &after[end + 1..];
+        } else if let Some((name, after)) = ["slow", "emphasis"]
+            .into_iter()
+            .find_map(|name| Some((name, tag.strip_prefix(&format!("[{name}]"))?)))
+        {
+            let closing = format!("[/{name}]");
+            let end = after
+                .find(&closing)
+                .ok_or(format!("[{name}] without closing {closing}"))?;
+            push_text(&mut parts, &mut current);
+            let inner = after[..end].trim().to_string();
+            if !inner.is_empty() {
+                parts.push(match name {
+                    "slow" => Markup::Slow(inner),
+                    _ => Markup::Emphasis(inner),
+                });
+            }
+            rest = &after[end + closing.len()..];
+        } else {
+            current.push('[');
+            rest = &tag[1..];
+        }
+    }
+    current.push_str(rest);
+    push_text(&mut parts, &mut current);
+    Ok(parts)
+}
+
+#[test]
+fn test_parse_markup() {
+    let parts = parse_markup("Foo [pause 1.5s] bar [slow]baz[/slow] [qux].").unwrap();
+    assert_eq!(
+        parts,
+        vec![
+            Markup::Text("Foo".to_string()),
+            Markup::Pause(chrono::Duration::milliseconds(1500)),
+            Markup::Text("bar".to_string()),
+            Markup::Slow("baz".to_string()),
+            Markup::Text("[qux].".to_string()),
+        ]
+    );
+    let parts = parse_markup("[pause] [pauses]").unwrap();
+    assert_eq!(
+        parts,
+        vec![
+            Markup::Pause(chrono::Duration::seconds(1)),
+            Markup::Text("[pauses]".to_string()),
+        ]
+    );
+    assert!(parse_markup("[slow]foo").is_err());
+    assert_eq!(
+        parse_markup("[emphasis]foo[/emphasis]"),
+        Ok(vec![Markup::Emphasis("foo".to_string())])
+    );
+    assert!(parse_markup("[pause 1]").is_err());
+}
+
+/// Whether the parts contain markup besides plain text.
+pub(crate) fn has_markup(parts: &[Markup]) -> bool {
+    parts.iter().any(|part| !matches!(part, Markup::Text(_)))
+}
+
+/// Join two parts of text with a space unless the second part starts with
+/// punctuation.
+fn join_text(text: &mut String, next: &str) {
+    let punctuation = next.starts_with(['.', ',', ';', ':', '!', '?', ')']);
+    if !text.is_empty() && !punctuation {
+        text.push(' ');
+    }
+    text.push_str(next);
+}
+
+/// The text of the parts without the markup.
+pub(crate) fn plain_text(parts: &[Markup]) -> String {
+    let mut result = String::new();
+    for part in parts {
+        match part {
+            Markup::Text(text) | Markup::Slow(text) | Markup::Emphasis(text) => {
+                join_text(&mut result, text)
+            }
+            Markup::Pause(_) => {}
+        }
+    }
+    result
+}
+
+/// The text without markup, for example, for subtitles.
+pub(crate) fn strip_markup(text: &str) -> String {
+    match parse_markup(text) {
+        Ok(parts) => plain_text(&parts),
+        Err(_) => text.to_string(),
+    }
+}
+
+/// Read emphasized text like the surrounding text.
+///
+/// The emphasis is merged into the neighbouring text, so that the text is
+/// still sent in one request.
+pub(crate) fn without_emphasis(parts: Vec<Markup>) -> Vec<Markup> {
+    let mut result = Vec::new();
+    for part in parts {
+        match (result.last_mut(), part) {
+            (Some(Markup::Text(text)), Markup::Text(next) | Markup::Emphasis(next)) => {
+                join_text(text, &next)
+            }
+            (_, Markup::Emphasis(next)) => result.push(Markup::Text(next)),
+            (_, part) => result.push(part),
+        }
+    }
+    result
+}
+
+#[test]
+fn test_without_emphasis() {
+    let parts = parse_markup("A [emphasis]B[/emphasis], C [pause] D").unwrap();
+    assert_eq!(
+        without_emphasis(parts),
+        vec![
+            Markup::Text("A B, C".to_string()),
+            Markup::Pause(chrono::Duration::seconds(1)),
+            Markup::Text("D".to_string()),
+        ]
+    );
+}
+
+pub(crate) fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Translate the parts to SSML.
+pub(crate) fn ssml(parts: &[Markup]) -> String {
+    let body = parts
+        .iter()
+        .map(|part| match part {
+            Markup::Text(text) => escape_xml(text),
+            Markup::Slow(text) => format!("<prosody rate=\"slow\">{}</prosody>", escape_xml(text)),
+            Markup::Emphasis(text) => format!("<emphasis>{}</emphasis>", escape_xml(text)),
+            Markup::Pause(duration) => {
+                format!("<break time=\"{}ms\"/>", duration.num_milliseconds())
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ");
+    format!("<speak>{body}</speak>")
+}
+
+#[test]
+fn test_ssml() {
+    let parts = parse_markup("A & B [pause 500ms] [slow]C[/slow]").unwrap();
+    assert_eq!(
+        ssml(&parts),
+        "<speak>A &amp; B <break time=\"500ms\"/> <prosody rate=\"slow\">C</prosody></speak>"
+    );
+    assert_eq!(strip_markup("A [pause 1s] [slow]B[/slow] [c]"), "A B [c]");
+}
+
+/// Check that the text is an SSML document with `<speak>` as root element.
+pub(crate) fn validate_ssml(text: &str) -> Result<(), String> {
+    let document = roxmltree::Document::parse(text).map_err(|e| format!("invalid SSML: {e}"))?;
+    let root = document.root_element().tag_name().name();
+    if root != "speak" {
+        return Err(format!(
+            "invalid SSML: expected <speak> as root element, got <{root}>"
+        ));
+    }
+    Ok(())
+}
+
+/// Total duration of the `<break>` elements in the SSML document in seconds.
+///
+/// Breaks without a `time`, such as `<break strength="strong"/>`, are counted
+/// as one second.
+pub(crate) fn ssml_pauses(text: &str) -> f64 {
+    let Ok(document) = roxmltree::Document::parse(text) else {
+        return 0.0;
+    };
+    document
+        .descendants()
+        .filter(|node| node.tag_name().name() == "break")
+        .map(|node| {
+            node.attribute("time")
+                .and_then(|time| parse_duration(time).ok())
+                .map_or(1.0, |duration| duration.num_milliseconds() as f64 / 1000.0)
+        })
+        .sum()
+}
+
+#[test]
+fn test_ssml_pauses() {
+    let text = "<speak>A <break time=\"10s\"/> B <break time=\"500ms\"/><break/></speak>";
+    assert_eq!(ssml_pauses(text), 11.5);
+    assert_eq!(ssml_pauses("<speak>A"), 0.0);
+}
+
+/// The text of the SSML document without the tags.
+///
+/// Sentences, paragraphs, and breaks are separated by a space, while inline
+/// elements such as `<emphasis>` are not. Returns the original text if the
+/// document is not valid.
+pub(crate) fn strip_ssml(text: &str) -> String {
+    fn push_text(node: roxmltree::Node, result: &mut String) {
+        if let Some(text) = node.text().filter(|_| node.is_text()) {
+            result.push_str(text);
+            return;
+        }
+        let block = matches!(node.tag_name().name(), "s" | "p" | "break");
+        if block {
+            result.push(' ');
+        }
+        for child in node.children() {
+            push_text(child, result);
+        }
+        if block {
+            result.push(' ');
+        }
+    }
+    let Ok(document) = roxmltree::Document::parse(text) else {
+        return text.to_string();
+    };
+    let mut result = String::new();
+    push_text(document.root(), &mut result);
+    result.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[test]
+fn test_strip_ssml() {
+    let text = "<speak>A &amp; B <break time=\"1s\"/><emphasis>C</emphasis>.</speak>";
+    assert_eq!(validate_ssml(text), Ok(()));
+    assert_eq!(strip_ssml(text), "A & B C.");
+    let text = "<speak><s>One.</s><s>Two.</s><p>Three<break/>four.</p></speak>";
+    assert_eq!(strip_ssml(text), "One. Two. Three four.");
+    assert!(validate_ssml("<speak>A").is_err());
+    let err = validate_ssml("<p>A</p>").unwrap_err();
+    assert!(err.contains("got <p>"), "{err}");
+}
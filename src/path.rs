@@ -2,16 +2,6 @@ use crate::slide::Slide;
 use std::path::Path;
 use std::path::PathBuf;
 
-pub trait PathStr {
-    fn to_string(&self) -> String;
-}
-
-impl PathStr for PathBuf {
-    fn to_string(&self) -> String {
-        self.to_str().unwrap().to_string()
-    }
-}
-
 pub fn audio_path(dir: &str, slide: &Slide, audio_ext: &str) -> PathBuf {
     let idx = slide.idx;
     let filename = format!("{idx}.{audio_ext}");
@@ -24,8 +14,44 @@ pub fn image_path(dir: &str, slide: &Slide) -> PathBuf {
     Path::new(dir).join("image").join(filename)
 }
 
-pub fn audio_cache_key_path(dir: &str, slide: &Slide) -> PathBuf {
+/// Path to the video segment of a slide with the given hash.
+pub fn segment_path(dir: &str, hash: &str) -> PathBuf {
+    let filename = format!("{hash}.mkv");
+    Path::new(dir).join("video").join(filename)
+}
+
+/// Default directory for the audio cache.
+///
+/// This is `$XDG_CACHE_HOME/trv` or `~/.cache/trv`. The cache is shared
+/// between presentations and out directories.
+pub fn default_cache_dir() -> PathBuf {
+    let base = match std::env::var("XDG_CACHE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let home = std::env::var("HOME").expect("HOME is not set");
+            Path::new(&home).join(".cache")
+        }
+    };
+    base.join("trv")
+}
+
+/// Path to the cached audio with the given hash.
+pub fn cached_audio_path(cache_dir: &Path, hash: &str, audio_ext: &str) -> PathBuf {
+    let filename = format!("{hash}.{audio_ext}");
+    cache_dir.join("audio").join(filename)
+}
+
+/// Path to the key of the cached audio with the given hash.
+pub fn cache_key_path(cache_dir: &Path, hash: &str) -> PathBuf {
+    let filename = format!("{hash}.cache_key");
+    cache_dir.join("audio").join(filename)
+}
+
+/// Path to the audio of one segment of a slide.
+///
+/// Segments are used when a slide has multiple speakers. Counting starts at 1.
+pub fn audio_segment_path(dir: &str, slide: &Slide, segment: usize, audio_ext: &str) -> PathBuf {
     let idx = slide.idx;
-    let filename = format!("{idx}.audio.cache_key");
+    let filename = format!("{idx}.{segment}.{audio_ext}");
     Path::new(dir).join("audio").join(filename)
 }
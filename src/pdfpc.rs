@@ -0,0 +1,154 @@
+use serde_json::Value;
+
+/// An entry of the pdfpc metadata that polylux writes into the document.
+///
+/// The entries can be queried via `typst query <input> <pdfpc> --field=value`.
+/// Each page starts with a `NewSlide` entry followed by entries that describe
+/// the page. Entries before the first page configure the presentation.
+#[derive(Debug, PartialEq)]
+enum Entry {
+    NewSlide,
+    /// Index of the page in the document, starting at 0.
+    Idx(usize),
+    /// Number of the logical slide, starting at 1.
+    LogicalSlide(usize),
+    Note(String),
+    HiddenSlide,
+    /// Entries that trv doesn't use, such as `Overlay`, `Duration`, or
+    /// `EndSlide`. Overlays are grouped via the logical slide instead.
+    /// `Duration` is the planned length of the talk in minutes for the pdfpc
+    /// timer, while trv times each slide by the length of its audio.
+    Other,
+}
+
+/// A page of the presentation according to the pdfpc metadata.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Page {
+    /// Index of the page in the document, starting at 0.
+    pub idx: usize,
+    /// Number of the logical slide, starting at 1.
+    ///
+    /// Pages with the same logical slide are overlays of the same slide.
+    pub logical_slide: Option<usize>,
+    pub note: Option<String>,
+    /// Whether the page is hidden in the presentation.
+    pub hidden: bool,
+}
+
+/// Parse an entry such as `{"t": "Idx", "v": 0}`.
+fn parse_entry(i: usize, value: &Value) -> Result<Entry, String> {
+    let error = |message: &str| format!("Invalid pdfpc entry {i} ({value}): {message}");
+    let t = value
+        .get("t")
+        .and_then(Value::as_str)
+        .ok_or_else(|| error("expected a string in field `t`"))?;
+    let v = value.get("v");
+    let number = || {
+        v.and_then(Value::as_u64)
+            .map(|v| v as usize)
+            .ok_or_else(|| error("expected a number in field `v`"))
+    };
+    let entry = match t {
+        "NewSlide" => Entry::NewSlide,
+        "Idx" => Entry::Idx(number()?),
+        "LogicalSlide" => Entry::LogicalSlide(number()?),
+        "Note" => {
+            let note = v
+                .and_then(Value::as_str)
+                .ok_or_else(|| error("expected a string in field `v`"))?;
+            Entry::Note(note.to_string())
+        }
+        "HiddenSlide" => Entry::HiddenSlide,
+        _ => Entry::Other,
+    };
+    Ok(entry)
+}
+
+/// Parse the pdfpc metadata into pages.
+pub(crate) fn parse_pages(values: &[Value]) -> Result<Vec<Page>, String> {
+    // Pages and whether the page had an `Idx` entry.
+    let mut pages: Vec<(Page, bool)> = Vec::new();
+    for (i, value) in values.iter().enumerate() {
+        let entry = parse_entry(i, value)?;
+        if entry == Entry::NewSlide {
+            pages.push((Page::default(), false));
+            continue;
+        }
+        let Some((page, has_idx)) = pages.last_mut() else {
+            if entry != Entry::Other {
+                return Err(format!(
+                    "Invalid pdfpc entry {i} ({value}): expected NewSlide before it"
+                ));
+            }
+            continue;
+        };
+        match entry {
+            Entry::NewSlide => unreachable!(),
+            Entry::Idx(idx) => {
+                page.idx = idx;
+                *has_idx = true;
+            }
+            Entry::LogicalSlide(logical_slide) => page.logical_slide = Some(logical_slide),
+            Entry::Note(note) => {
+                // Multiple notes on one page are read one after the other.
+                page.note = Some(match page.note.take() {
+                    Some(previous) => format!("{previous}\n\n{note}"),
+                    None => note,
+                });
+            }
+            Entry::HiddenSlide => page.hidden = true,
+            Entry::Other => {}
+        }
+    }
+    let mut result = Vec::new();
+    for (n, (page, has_idx)) in pages.into_iter().enumerate() {
+        if !has_idx {
+            return Err(format!(
+                "Page {} has no Idx entry in the pdfpc metadata",
+                n + 1
+            ));
+        }
+        result.push(page);
+    }
+    Ok(result)
+}
+
+#[test]
+fn test_parse_pages() {
+    let values: Vec<Value> = serde_json::from_str(
+        r#"[
+            {"t": "Duration", "v": 5},
+            {"t": "NewSlide"},
+            {"t": "Idx", "v": 0},
+            {"t": "Overlay", "v": 0},
+            {"t": "LogicalSlide", "v": 1},
+            {"t": "Note", "v": "foo"},
+            {"t": "NewSlide"},
+            {"t": "Idx", "v": 1},
+            {"t": "Overlay", "v": 0},
+            {"t": "LogicalSlide", "v": 2},
+            {"t": "HiddenSlide"},
+            {"t": "EndSlide"}
+        ]"#,
+    )
+    .unwrap();
+    let pages = parse_pages(&values).unwrap();
+    assert_eq!(pages.len(), 2);
+    assert_eq!(pages[0].note, Some("foo".to_string()));
+    assert_eq!(pages[1].idx, 1);
+    assert_eq!(pages[1].logical_slide, Some(2));
+    assert_eq!(pages[1].note, None);
+    assert!(pages[1].hidden);
+
+    let values: Vec<Value> = serde_json::from_str(r#"[{"t": "Note", "v": "foo"}]"#).unwrap();
+    let err = parse_pages(&values).unwrap_err();
+    assert!(err.contains("expected NewSlide"), "{err}");
+
+    let values: Vec<Value> = serde_json::from_str(r#"[{"t": "NewSlide"}, {"t": "Idx"}]"#).unwrap();
+    let err = parse_pages(&values).unwrap_err();
+    assert!(err.starts_with("Invalid pdfpc entry 1"), "{err}");
+
+    let values: Vec<Value> = serde_json::from_str(r#"[{"t": "NewSlide"}]"#).unwrap();
+    let err = parse_pages(&values).unwrap_err();
+    assert!(err.contains("no Idx entry"), "{err}");
+}
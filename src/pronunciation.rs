@@ -0,0 +1,164 @@
+use crate::markup::escape_xml;
+use crate::markup::Markup;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+/// Whether the character separates words.
+fn is_boundary(c: Option<char>) -> bool {
+    c.is_none_or(|c| !c.is_alphanumeric() && c != '_')
+}
+
+/// Replace the written forms in the text by their spoken forms.
+///
+/// Only whole words are replaced, so `trv` matches in "Run trv." but not in
+/// "trvx". When multiple written forms match at the same position, the
+/// longest one wins. Returns the text and the entries that were applied.
+pub(crate) fn pronounce(
+    text: &str,
+    pronunciations: &HashMap<String, String>,
+) -> (String, BTreeMap<String, String>) {
+    let mut written = pronunciations
+        .keys()
+        .filter(|written| !written.is_empty())
+        .collect::<Vec<&String>>();
+    written.sort_by_key(|written| std::cmp::Reverse(written.len()));
+    let mut result = String::new();
+    let mut used = BTreeMap::new();
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let at_boundary = is_boundary(text[..i].chars().next_back());
+        let matched = written.iter().find(|written| {
+            rest.starts_with(written.as_str())
+                && (at_boundary || is_boundary(written.chars().next()))
+                && (is_boundary(rest[written.len()..].chars().next())
+                    || is_boundary(written.chars().next_back()))
+        });
+        match matched {
+            Some(written) => {
+                let spoken = &pronunciations[*written];
+                result.push_str(spoken);
+                used.insert(written.to_string(), spoken.clone());
+                i += written.len();
+            }
+            None => {
+                let c = rest.chars().next().unwrap();
+                result.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+    (result, used)
+}
+
+#[test]
+fn test_pronounce() {
+    let pronunciations = HashMap::from([
+        ("trv".to_string(), "T R V".to_string()),
+        ("Typst".to_string(), "Tipst".to_string()),
+        ("Typst CLI".to_string(), "Tipst command line".to_string()),
+        ("C++".to_string(), "C plus plus".to_string()),
+        ("unused".to_string(), "not used".to_string()),
+    ]);
+    let (text, used) = pronounce("Run trv, not trvx, with Typst CLI or C++.", &pronunciations);
+    assert_eq!(
+        text,
+        "Run T R V, not trvx, with Tipst command line or C plus plus."
+    );
+    assert_eq!(
+        used.keys().collect::<Vec<_>>(),
+        vec!["C++", "Typst CLI", "trv"]
+    );
+    let (text, used) = pronounce("Nothing to replace.", &pronunciations);
+    assert_eq!(text, "Nothing to replace.");
+    assert!(used.is_empty());
+}
+
+/// Apply the pronunciations to the text of the parsed markup.
+///
+/// Only the text parts are changed, so written forms never match inside
+/// markup such as `[pause 1s]`.
+pub(crate) fn pronounce_markup(
+    parts: Vec<Markup>,
+    pronunciations: &HashMap<String, String>,
+) -> (Vec<Markup>, BTreeMap<String, String>) {
+    let mut used = BTreeMap::new();
+    let mut apply = |text: String| {
+        let (text, applied) = pronounce(&text, pronunciations);
+        used.extend(applied);
+        text
+    };
+    let parts = parts
+        .into_iter()
+        .map(|part| match part {
+            Markup::Text(text) => Markup::Text(apply(text)),
+            Markup::Slow(text) => Markup::Slow(apply(text)),
+            Markup::Emphasis(text) => Markup::Emphasis(apply(text)),
+            Markup::Pause(duration) => Markup::Pause(duration),
+        })
+        .collect();
+    (parts, used)
+}
+
+/// Apply the pronunciations to the text nodes of the SSML document.
+///
+/// Tags and attributes are left as they are. Text inside `<sub>` and
+/// `<phoneme>` is skipped since it already specifies how it is read.
+pub(crate) fn pronounce_ssml(
+    text: &str,
+    pronunciations: &HashMap<String, String>,
+) -> Result<(String, BTreeMap<String, String>), String> {
+    let document = roxmltree::Document::parse(text).map_err(|e| format!("invalid SSML: {e}"))?;
+    let mut result = String::new();
+    let mut used = BTreeMap::new();
+    let mut end = 0;
+    for node in document.descendants().filter(|node| node.is_text()) {
+        let in_alias = node
+            .ancestors()
+            .any(|node| matches!(node.tag_name().name(), "sub" | "phoneme"));
+        if in_alias {
+            continue;
+        }
+        let (spoken, applied) = pronounce(node.text().unwrap(), pronunciations);
+        if applied.is_empty() {
+            continue;
+        }
+        let range = node.range();
+        result.push_str(&text[end..range.start]);
+        result.push_str(&escape_xml(&spoken));
+        end = range.end;
+        used.extend(applied);
+    }
+    result.push_str(&text[end..]);
+    Ok((result, used))
+}
+
+#[test]
+fn test_pronounce_markup_and_ssml() {
+    let pronunciations = HashMap::from([
+        ("trv".to_string(), "T R V".to_string()),
+        ("pause".to_string(), "paws".to_string()),
+        ("speak".to_string(), "talk".to_string()),
+    ]);
+    let parts = crate::markup::parse_markup("Run trv [pause 1s] and [slow]pause[/slow].").unwrap();
+    let (parts, used) = pronounce_markup(parts, &pronunciations);
+    assert_eq!(
+        parts,
+        vec![
+            Markup::Text("Run T R V".to_string()),
+            Markup::Pause(chrono::Duration::seconds(1)),
+            Markup::Text("and".to_string()),
+            Markup::Slow("paws".to_string()),
+            Markup::Text(".".to_string()),
+        ]
+    );
+    assert_eq!(used.keys().collect::<Vec<_>>(), vec!["pause", "trv"]);
+    let ssml = "<speak>Speak trv &amp; <sub alias=\"trv\">trv</sub>.</speak>";
+    let (ssml, used) = pronounce_ssml(ssml, &pronunciations).unwrap();
+    assert_eq!(
+        ssml,
+        "<speak>Speak T R V &amp; <sub alias=\"trv\">trv</sub>.</speak>"
+    );
+    assert_eq!(used.keys().collect::<Vec<_>>(), vec!["trv"]);
+    assert!(pronounce_ssml("<speak>trv", &pronunciations).is_err());
+}
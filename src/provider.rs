@@ -0,0 +1,73 @@
+use std::fmt;
+use std::str::FromStr;
+use transformrs::Provider;
+
+/// Text-to-speech provider.
+///
+/// Next to the providers that are available via transformrs, trv supports
+/// providers that run locally.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum TtsProvider {
+    /// A provider with an API that is called via transformrs.
+    Api(Provider),
+    /// A local program that is run for each slide.
+    ///
+    /// The program is set via `command` in the trv config.
+    Command,
+    /// Generates silent audio without calling any service.
+    ///
+    /// The duration of the audio is derived from the number of words in the
+    /// speaker note. This is useful for tests and for drafting the timing of
+    /// the slides without spending API credits.
+    Mock,
+}
+
+impl FromStr for TtsProvider {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "command" => Ok(TtsProvider::Command),
+            "mock" => Ok(TtsProvider::Mock),
+            _ => Ok(TtsProvider::Api(Provider::from_str(s)?)),
+        }
+    }
+}
+
+#[test]
+fn test_provider_from_str() {
+    let provider = TtsProvider::from_str("command").unwrap();
+    assert_eq!(provider, TtsProvider::Command);
+    let provider = TtsProvider::from_str("mock").unwrap();
+    assert_eq!(provider, TtsProvider::Mock);
+    let provider = TtsProvider::from_str("google").unwrap();
+    assert_eq!(provider, Provider::Google);
+    assert!(TtsProvider::from_str("foo").is_err());
+}
+
+impl TtsProvider {
+    /// Whether the provider accepts SSML input.
+    pub(crate) fn supports_ssml(&self) -> bool {
+        self == &Provider::Google
+    }
+}
+
+impl fmt::Display for TtsProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TtsProvider::Api(provider) => write!(f, "{provider}"),
+            TtsProvider::Command => write!(f, "Command"),
+            TtsProvider::Mock => write!(f, "Mock"),
+        }
+    }
+}
+
+/// Allows checks such as `provider == &Provider::Google`.
+impl PartialEq<Provider> for TtsProvider {
+    fn eq(&self, other: &Provider) -> bool {
+        match self {
+            TtsProvider::Api(provider) => provider == other,
+            _ => false,
+        }
+    }
+}
@@ -1,6 +1,18 @@
+use crate::markdown::speakable;
+use crate::markup::parse_markup;
+use crate::markup::strip_markup;
+use crate::markup::strip_ssml;
+use crate::markup::validate_ssml;
+use crate::pdfpc::parse_pages;
+use crate::pdfpc::Page;
+use crate::verbalize::Language;
+use crate::video::Transition;
+use crate::Config;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::Value;
+use std::path::Path;
+use std::path::PathBuf;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Slide {
@@ -9,6 +21,127 @@ pub struct Slide {
     /// Counting starts at 1 and ends at n.
     pub idx: usize,
     pub speaker_note: String,
+    /// Settings that override the config for this slide only.
+    pub config: SlideConfig,
+    /// Names of the speakers in the `[voices]` table of the trv config.
+    ///
+    /// Only tags such as `[host]:` with one of these names start a segment,
+    /// so that text such as "Remember [important]: save first" is read as is.
+    #[serde(default)]
+    pub speakers: Vec<String>,
+}
+
+/// Settings that can be overridden per slide.
+///
+/// These are set via a front matter block at the start of the speaker note.
+/// The block is written in TOML, just like the trv config:
+///
+/// ```md
+/// ---
+/// voice = "af_bella"
+/// speed = 1.1
+/// ---
+/// This slide is read by a different voice.
+/// ```
+///
+/// Fields that are not set fall back to the value in the trv config.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlideConfig {
+    pub voice: Option<String>,
+    pub speed: Option<f64>,
+    pub seed: Option<u64>,
+    pub language_code: Option<String>,
+    /// Transition into this slide.
+    pub transition: Option<Transition>,
+    /// Duration of a slide without narration, for example `"3s"` or `"500ms"`.
+    ///
+    /// The slide is shown for this duration in silence. Useful for title
+    /// cards or for giving the viewer time to think.
+    pub duration: Option<String>,
+    /// Whether the speaker note is SSML that is sent as-is to the provider.
+    ///
+    /// Overrides `ssml` from the trv config.
+    pub ssml: Option<bool>,
+    /// Whether numbers, units, dates, and math are written out in words.
+    ///
+    /// Overrides `verbalize` from the trv config.
+    pub verbalize: Option<bool>,
+    /// Pre-recorded audio for the slide, relative to the input file.
+    ///
+    /// The recording is used instead of text-to-speech. The speaker note is
+    /// still used for the subtitles.
+    pub audio: Option<PathBuf>,
+}
+
+/// Parse a duration such as `3s`, `1.5s`, or `500ms`.
+pub fn parse_duration(text: &str) -> Result<chrono::Duration, String> {
+    let text = text.trim();
+    let (number, millis_per_unit) = if let Some(number) = text.strip_suffix("ms") {
+        (number, 1.0)
+    } else if let Some(number) = text.strip_suffix('s') {
+        (number, 1000.0)
+    } else {
+        return Err(format!(
+            "invalid duration `{text}`, expected for example `3s` or `500ms`"
+        ));
+    };
+    match number.trim().parse::<f64>() {
+        Ok(number) if 0.0 <= number => Ok(chrono::Duration::milliseconds(
+            (number * millis_per_unit) as i64,
+        )),
+        _ => Err(format!(
+            "invalid duration `{text}`, expected for example `3s` or `500ms`"
+        )),
+    }
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("3s"), Ok(chrono::Duration::seconds(3)));
+    assert_eq!(
+        parse_duration("1.5s"),
+        Ok(chrono::Duration::milliseconds(1500))
+    );
+    assert_eq!(
+        parse_duration("500ms"),
+        Ok(chrono::Duration::milliseconds(500))
+    );
+    assert!(parse_duration("3").is_err());
+    assert!(parse_duration("-1s").is_err());
+}
+
+/// Split the front matter from the speaker note.
+///
+/// Returns the front matter (if any) and the remaining text.
+fn split_front_matter(text: &str) -> (Option<String>, String) {
+    let mut lines = text.lines().skip_while(|line| line.trim().is_empty());
+    if lines.next().map(|line| line.trim()) != Some("---") {
+        return (None, text.to_string());
+    }
+    let mut front_matter = Vec::new();
+    for line in lines.by_ref() {
+        if line.trim() == "---" {
+            let rest = lines.collect::<Vec<&str>>().join("\n");
+            return (Some(front_matter.join("\n")), rest);
+        }
+        front_matter.push(line.trim());
+    }
+    // Without a closing delimiter, the text is not a front matter block.
+    (None, text.to_string())
+}
+
+#[test]
+fn test_split_front_matter() {
+    let text = "\n    ---\n    voice = \"af_bella\"\n    ---\n    foo.\n";
+    let (front_matter, rest) = split_front_matter(text);
+    assert_eq!(front_matter, Some("voice = \"af_bella\"".to_string()));
+    assert_eq!(trim_speaker_note(&rest), "foo.");
+
+    let text = "foo.\n---\nbar.";
+    let (front_matter, rest) = split_front_matter(text);
+    assert_eq!(front_matter, None);
+    assert_eq!(rest, text);
 }
 
 /// Cleanup the speaker note.
@@ -38,19 +171,370 @@ fn test_trim_speaker_note() {
     let text = "\n    foo.\n   bar.\n\nbaz.\n\n ";
     let out = trim_speaker_note(text);
     assert_eq!(out, "foo. bar.\n\nbaz.");
+
+    let out = trim_speaker_note("foo [pause 1s]\n[slow]bar[/slow]");
+    assert_eq!(out, "foo [pause 1s] [slow]bar[/slow]");
+}
+
+/// A part of a speaker note that is spoken by one speaker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    /// Name of the speaker as used in the `[voices]` table of the trv config.
+    ///
+    /// `None` means that the segment is spoken by the default voice.
+    pub speaker: Option<String>,
+    pub text: String,
+}
+
+/// Parse a speaker tag such as `[host]:` at the start of the text.
+///
+/// Returns the name of the speaker and the length of the tag.
+pub(crate) fn speaker_tag(text: &str) -> Option<(&str, usize)> {
+    let rest = text.strip_prefix('[')?;
+    let end = rest.find("]:")?;
+    let name = &rest[..end];
+    let is_name = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    if name.is_empty() || !name.chars().all(is_name) {
+        return None;
+    }
+    Some((name, end + "[]:".len()))
+}
+
+/// Split the text into segments per speaker.
+///
+/// Tags of names that are not in `speakers` are kept as text.
+fn split_segments(text: &str, speakers: &[String]) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut speaker = None;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if c != '[' || (i != 0 && !text[..i].ends_with(char::is_whitespace)) {
+            continue;
+        }
+        let tag = speaker_tag(&text[i..]).filter(|(name, _)| speakers.iter().any(|s| s == name));
+        if let Some((name, len)) = tag {
+            let segment_text = text[start..i].trim();
+            if !segment_text.is_empty() {
+                segments.push(Segment {
+                    speaker: speaker.clone(),
+                    text: segment_text.to_string(),
+                });
+            }
+            speaker = Some(name.to_string());
+            start = i + len;
+        }
+    }
+    let segment_text = text[start..].trim();
+    if !segment_text.is_empty() || segments.is_empty() {
+        segments.push(Segment {
+            speaker,
+            text: segment_text.to_string(),
+        });
+    }
+    segments
+}
+
+#[test]
+fn test_split_segments() {
+    let speakers = vec!["host".to_string(), "guest".to_string()];
+    let segments = split_segments(
+        "Welcome. [host]: Hi! [guest]: Hello [there]. [host]: Remember [important]: bye.",
+        &speakers,
+    );
+    let expected = [
+        (None, "Welcome."),
+        (Some("host"), "Hi!"),
+        (Some("guest"), "Hello [there]."),
+        (Some("host"), "Remember [important]: bye."),
+    ];
+    assert_eq!(segments.len(), expected.len());
+    for (segment, (speaker, text)) in segments.iter().zip(expected) {
+        assert_eq!(segment.speaker.as_deref(), speaker);
+        assert_eq!(segment.text, text);
+    }
+
+    let segments = split_segments("No speakers [here]: at all.", &[]);
+    assert_eq!(segments.len(), 1);
+    assert_eq!(segments[0].speaker, None);
 }
 
 impl Slide {
-    fn new(idx: &Value, speaker_note: &Value) -> Self {
-        let idx = idx.get("v").and_then(|v| v.as_u64()).unwrap();
+    /// Split the speaker note into segments per speaker.
+    ///
+    /// Speakers are marked with tags such as `[host]:` and `[guest]:`. Text
+    /// before the first tag is spoken by the default voice.
+    pub fn segments(&self) -> Vec<Segment> {
+        if self.is_ssml() {
+            // Speaker tags are not supported inside SSML.
+            return vec![Segment {
+                speaker: None,
+                text: self.speaker_note.clone(),
+            }];
+        }
+        split_segments(&self.speaker_note, &self.speakers)
+    }
+
+    /// Whether the speaker note is SSML.
+    pub fn is_ssml(&self) -> bool {
+        self.config.ssml == Some(true)
+    }
+
+    /// The speaker note without markup, for example, for subtitles.
+    pub fn plain_text(&self) -> String {
+        if self.is_ssml() {
+            return strip_ssml(&self.speaker_note);
+        }
+        self.segments()
+            .iter()
+            .map(|segment| strip_markup(&segment.text))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// Duration of the slide if it is a slide without narration.
+    pub fn silent_duration(&self) -> Option<chrono::Duration> {
+        let duration = self.config.duration.as_ref()?;
+        Some(parse_duration(duration).unwrap())
+    }
+}
+
+/// Parse the front matter of the speaker note of slide `idx`.
+fn slide_config(idx: usize, front_matter: Option<String>) -> Result<SlideConfig, String> {
+    let config = match front_matter {
+        Some(front_matter) => toml::from_str::<SlideConfig>(&front_matter)
+            .map_err(|e| format!("Slide {idx}: failed to parse front matter: {e}"))?,
+        None => SlideConfig::default(),
+    };
+    if let Some(duration) = &config.duration {
+        parse_duration(duration).map_err(|e| format!("Slide {idx}: {e}"))?;
+    }
+    Ok(config)
+}
+
+/// Group the pages by logical slide.
+///
+/// A logical slide consists of multiple pages when it has overlays, for
+/// example, due to `#uncover` or `#only` in polylux.
+fn logical_slides(pages: &[Page]) -> Vec<Vec<&Page>> {
+    let mut groups: Vec<Vec<&Page>> = Vec::new();
+    for page in pages {
+        match groups.last_mut() {
+            Some(group)
+                if page.logical_slide.is_some() && group[0].logical_slide == page.logical_slide =>
+            {
+                group.push(page)
+            }
+            _ => groups.push(vec![page]),
+        }
+    }
+    groups
+}
+
+/// Marker that splits a speaker note into the parts for each overlay.
+const NEXT_MARKER: &str = "[next]";
+
+/// Duration of slides (and overlays) without a speaker note.
+///
+/// They are shown in silence, so that the video contains all visible slides.
+const SILENT_DURATION: &str = "3s";
+
+/// Convert the pages into the slides of the video.
+///
+/// A logical slide with overlays is read once. Without `[next]` markers in
+/// the speaker note, the whole note is read while the last overlay is shown.
+/// With markers, the note is split into one part per overlay, so that each
+/// overlay is shown while its part is read.
+fn slides_from_pages(pages: &[Page], deck: &Config) -> Result<Vec<Slide>, String> {
+    let mut slides = Vec::new();
+    let pages = pages
+        .iter()
+        .filter(|page| {
+            if page.hidden {
+                tracing::info!("Slide {}: Skipping hidden slide", page.idx + 1);
+            }
+            !page.hidden
+        })
+        .cloned()
+        .collect::<Vec<Page>>();
+    let mut speakers = deck.voices.keys().cloned().collect::<Vec<String>>();
+    speakers.sort();
+    for overlays in logical_slides(&pages) {
         // Typst generates images starting at index 1.
-        let idx = idx as usize + 1;
-        let speaker_note = speaker_note.get("v").and_then(|v| v.as_str()).unwrap();
-        let speaker_note = trim_speaker_note(speaker_note);
-        Self { idx, speaker_note }
+        let first_idx = overlays[0].idx + 1;
+        // Polylux repeats the note on each overlay.
+        let note = overlays
+            .iter()
+            .find_map(|page| page.note.as_deref())
+            .unwrap_or_default();
+        let (front_matter, note) = split_front_matter(note);
+        let mut config = slide_config(first_idx, front_matter)?;
+        config.ssml = config.ssml.or(deck.ssml);
+        config.verbalize = config.verbalize.or(deck.verbalize);
+        let language_code = config
+            .language_code
+            .as_ref()
+            .or(deck.language_code.as_ref());
+        let supported = Language::from_code(language_code.map(|code| code.as_str())).is_some();
+        if config.verbalize == Some(true) && !supported {
+            tracing::warn!(
+                "Slide {first_idx}: Not verbalizing since language code {} is not supported",
+                language_code.unwrap()
+            );
+        }
+        let parts = note.split(NEXT_MARKER).collect::<Vec<&str>>();
+        if config.audio.is_some() && config.duration.is_some() {
+            return Err(format!(
+                "Slide {first_idx}: a slide can't have both an audio recording and a duration"
+            ));
+        }
+        if config.audio.is_some() && 1 < parts.len() {
+            return Err(format!(
+                "Slide {first_idx}: a slide with an audio recording can't be split by {NEXT_MARKER} markers"
+            ));
+        }
+        let overlays = if parts.len() == 1 {
+            vec![*overlays.last().unwrap()]
+        } else if parts.len() == overlays.len() {
+            overlays
+        } else {
+            return Err(format!(
+                "Slide {first_idx}: the speaker note is split into {} parts by {NEXT_MARKER} markers, \
+                 but the slide has {} overlays",
+                parts.len(),
+                overlays.len()
+            ));
+        };
+        for (page, part) in overlays.iter().zip(parts) {
+            let idx = page.idx + 1;
+            let part = if config.ssml != Some(true) && deck.markdown == Some(true) {
+                speakable(part)
+            } else {
+                part.to_string()
+            };
+            let mut speaker_note = trim_speaker_note(&part);
+            let mut config = config.clone();
+            if config.duration.is_some() {
+                if !speaker_note.is_empty() {
+                    tracing::warn!(
+                        "Slide {idx}: Not reading the speaker note since the slide has a duration"
+                    );
+                }
+                speaker_note.clear();
+            } else if speaker_note.is_empty() && config.audio.is_none() {
+                tracing::warn!(
+                    "Slide {idx}: No speaker note, so the slide is shown for {SILENT_DURATION} in silence"
+                );
+                config.duration = Some(SILENT_DURATION.to_string());
+            }
+            let slide = Slide {
+                idx,
+                speaker_note,
+                config,
+                speakers: speakers.clone(),
+            };
+            if slide.is_ssml() {
+                if !slide.speaker_note.is_empty() {
+                    validate_ssml(&slide.speaker_note).map_err(|e| format!("Slide {idx}: {e}"))?;
+                }
+            } else {
+                for segment in slide.segments() {
+                    parse_markup(&segment.text).map_err(|e| format!("Slide {idx}: {e}"))?;
+                }
+            }
+            slides.push(slide);
+        }
     }
+    Ok(slides)
 }
 
+#[test]
+fn test_slides_from_pages() {
+    let page = |idx, logical_slide, note: &str| Page {
+        idx,
+        logical_slide: Some(logical_slide),
+        note: Some(note.to_string()),
+        hidden: false,
+    };
+    let pages = vec![
+        page(0, 1, "foo"),
+        page(1, 1, "foo"),
+        page(2, 2, "---\nspeed = 1.2\n---\nbar [next] baz"),
+        page(3, 2, "---\nspeed = 1.2\n---\nbar [next] baz"),
+    ];
+    let slides = slides_from_pages(&pages, &Config::default()).unwrap();
+    let notes = slides
+        .iter()
+        .map(|slide| (slide.idx, slide.speaker_note.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(notes, vec![(2, "foo"), (3, "bar"), (4, "baz")]);
+    assert_eq!(slides[2].config.speed, Some(1.2));
+
+    let pages = vec![page(0, 1, "---\nduration = \"3s\"\n---\n")];
+    let slides = slides_from_pages(&pages, &Config::default()).unwrap();
+    assert_eq!(
+        slides[0].silent_duration(),
+        Some(chrono::Duration::seconds(3))
+    );
+
+    let mut pages = vec![page(0, 1, "a [next] "), page(1, 1, ""), page(2, 2, "")];
+    pages[2].note = None;
+    let slides = slides_from_pages(&pages, &Config::default()).unwrap();
+    let durations = slides
+        .iter()
+        .map(|slide| (slide.idx, slide.silent_duration()))
+        .collect::<Vec<_>>();
+    let silent = Some(chrono::Duration::seconds(3));
+    assert_eq!(durations, vec![(1, None), (2, silent), (3, silent)]);
+
+    let pages = vec![page(0, 1, "a [next] b [next] c"), page(1, 1, "")];
+    let err = slides_from_pages(&pages, &Config::default()).unwrap_err();
+    assert!(err.contains("3 parts"), "{err}");
+
+    let pages = vec![page(0, 1, "foo [slow]bar")];
+    let err = slides_from_pages(&pages, &Config::default()).unwrap_err();
+    assert!(err.starts_with("Slide 1: [slow]"), "{err}");
+
+    let pages = vec![page(0, 1, "---\nssml = true\n---\n<speak>foo")];
+    let err = slides_from_pages(&pages, &Config::default()).unwrap_err();
+    assert!(err.starts_with("Slide 1: invalid SSML"), "{err}");
+    let config = Config {
+        ssml: Some(true),
+        ..Default::default()
+    };
+    let pages = vec![page(0, 1, "<speak>foo [slow]</speak>")];
+    let slides = slides_from_pages(&pages, &config).unwrap();
+    assert_eq!(slides[0].plain_text(), "foo [slow]");
+
+    let config = Config {
+        verbalize: Some(true),
+        ..Default::default()
+    };
+    let pages = vec![
+        page(0, 1, "x^2 + 1"),
+        page(1, 2, "---\nverbalize = false\n---\nx^2 + 1"),
+    ];
+    let slides = slides_from_pages(&pages, &config).unwrap();
+    assert_eq!(slides[0].speaker_note, "x^2 + 1");
+    assert_eq!(slides[0].config.verbalize, Some(true));
+    assert_eq!(slides[1].config.verbalize, Some(false));
+
+    let pages = vec![page(0, 1, "---\naudio = \"intro.wav\"\n---\n")];
+    let slides = slides_from_pages(&pages, &Config::default()).unwrap();
+    assert_eq!(slides[0].config.audio, Some(PathBuf::from("intro.wav")));
+    assert_eq!(slides[0].speaker_note, "");
+
+    let note = "---\naudio = \"intro.wav\"\n---\na [next] b";
+    let pages = vec![page(0, 1, note), page(1, 1, note)];
+    let err = slides_from_pages(&pages, &Config::default()).unwrap_err();
+    assert!(err.contains("[next]"), "{err}");
+}
+
+#[cfg(feature = "embed-typst")]
+fn query_presenter_notes(input: &str) -> Value {
+    crate::world::query_presenter_notes(input)
+}
+
+#[cfg(not(feature = "embed-typst"))]
 fn query_presenter_notes(input: &str) -> Value {
     let output = std::process::Command::new("typst")
         .arg("query")
@@ -71,23 +555,37 @@ fn query_presenter_notes(input: &str) -> Value {
     }
 }
 
-pub fn slides(input: &str) -> Vec<Slide> {
+pub fn slides(input: &str, config: &Config) -> Vec<Slide> {
     let json = query_presenter_notes(input);
-
-    let values = json.as_array().expect("Expected JSON array");
-
-    let mut slides = Vec::new();
-
-    for i in 0..values.len() {
-        let note = &values[i];
-        if let Some(obj) = note.as_object() {
-            if let Some(t) = obj.get("t") {
-                if t == "NewSlide" {
-                    let idx = &values[i + 1];
-                    let speaker_note = &values[i + 4];
-                    let slide = Slide::new(idx, speaker_note);
-                    slides.push(slide);
-                }
+    let Some(values) = json.as_array() else {
+        tracing::error!("Expected a JSON array of pdfpc entries, got: {json}");
+        std::process::exit(1);
+    };
+    let pages = match parse_pages(values) {
+        Ok(pages) => pages,
+        Err(e) => {
+            tracing::error!("Failed to parse the speaker notes in {input}: {e}");
+            std::process::exit(1);
+        }
+    };
+    let mut slides = match slides_from_pages(&pages, config) {
+        Ok(slides) => slides,
+        Err(e) => {
+            tracing::error!("{e}");
+            std::process::exit(1);
+        }
+    };
+    let input_dir = Path::new(input).parent().unwrap();
+    for slide in &mut slides {
+        if let Some(audio) = &mut slide.config.audio {
+            *audio = input_dir.join(&audio);
+            if !audio.exists() {
+                tracing::error!(
+                    "Slide {}: audio file {} not found",
+                    slide.idx,
+                    audio.display()
+                );
+                std::process::exit(1);
             }
         }
     }
@@ -0,0 +1,206 @@
+use crate::slide::Slide;
+use crate::video::SlideDuration;
+use chrono::Duration;
+use std::path::Path;
+
+/// A subtitle that is shown from `start` until `end`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Cue {
+    pub start: Duration,
+    pub end: Duration,
+    pub text: String,
+}
+
+/// Split the text into sentences.
+///
+/// Paragraphs always end a sentence.
+pub(crate) fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    for paragraph in text.split("\n\n") {
+        let mut sentence = String::new();
+        let mut chars = paragraph.chars().peekable();
+        while let Some(c) = chars.next() {
+            sentence.push(c);
+            let at_boundary = chars.peek().is_none_or(|next| next.is_whitespace());
+            if matches!(c, '.' | '!' | '?') && at_boundary {
+                sentences.push(sentence.trim().to_string());
+                sentence.clear();
+            }
+        }
+        if !sentence.trim().is_empty() {
+            sentences.push(sentence.trim().to_string());
+        }
+    }
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
+
+#[test]
+fn test_split_sentences() {
+    let text = "Hello, world! Version 1.0 is out.\n\nIs it? Yes";
+    let sentences = split_sentences(text);
+    assert_eq!(
+        sentences,
+        vec!["Hello, world!", "Version 1.0 is out.", "Is it?", "Yes"]
+    );
+}
+
+/// Spread the sentences of the slide over the duration of its audio.
+///
+/// The time per sentence is proportional to the number of characters in the
+/// sentence, which is a reasonable approximation of the speaking time. The
+/// times are relative to the start of the slide.
+pub(crate) fn slide_cues(slide: &Slide, duration: &SlideDuration) -> Vec<Cue> {
+    let sentences = split_sentences(&slide.plain_text());
+    let total_chars = sentences
+        .iter()
+        .map(|sentence| sentence.chars().count())
+        .sum::<usize>();
+    let audio_ms = duration.audio.num_milliseconds();
+    let mut chars_so_far = 0;
+    let mut cues = Vec::new();
+    for sentence in sentences {
+        let start = audio_ms * chars_so_far as i64 / total_chars as i64;
+        chars_so_far += sentence.chars().count();
+        let end = audio_ms * chars_so_far as i64 / total_chars as i64;
+        cues.push(Cue {
+            start: Duration::milliseconds(start),
+            end: Duration::milliseconds(end),
+            text: sentence,
+        });
+    }
+    cues
+}
+
+/// Spread the sentences of each slide over the duration of its audio.
+///
+/// The cues of each slide are shifted by the start of its audio in the
+/// video, which is after the transition into the slide.
+fn cues(slides: &[Slide], durations: &[SlideDuration]) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    let mut offset = Duration::zero();
+    for (slide, duration) in slides.iter().zip(durations) {
+        // The slide overlaps with the previous slide during the transition
+        // and the audio starts after the transition.
+        offset -= duration.transition;
+        let audio_start = offset + duration.transition;
+        for cue in slide_cues(slide, duration) {
+            cues.push(Cue {
+                start: audio_start + cue.start,
+                end: audio_start + cue.end,
+                text: cue.text,
+            });
+        }
+        offset += duration.total();
+    }
+    cues
+}
+
+#[test]
+fn test_cues() {
+    let slides = vec![
+        Slide {
+            idx: 1,
+            speaker_note: "Foo. Bar baz.".to_string(),
+            config: Default::default(),
+            speakers: Vec::new(),
+        },
+        Slide {
+            idx: 2,
+            speaker_note: "Qux.".to_string(),
+            config: Default::default(),
+            speakers: Vec::new(),
+        },
+    ];
+    let durations = vec![
+        SlideDuration {
+            transition: Duration::zero(),
+            audio: Duration::milliseconds(1300),
+            pause: Duration::milliseconds(500),
+        },
+        SlideDuration {
+            transition: Duration::milliseconds(300),
+            audio: Duration::milliseconds(1000),
+            pause: Duration::milliseconds(500),
+        },
+    ];
+    let cues = cues(&slides, &durations);
+    let times = cues
+        .iter()
+        .map(|cue| (cue.start.num_milliseconds(), cue.end.num_milliseconds()))
+        .collect::<Vec<_>>();
+    assert_eq!(times, vec![(0, 433), (433, 1300), (1800, 2800)]);
+}
+
+/// Format the timestamp as `HH:MM:SS<separator>mmm`.
+///
+/// SRT uses a comma as separator while WebVTT uses a dot.
+fn format_timestamp(timestamp: &Duration, separator: char) -> String {
+    let ms = timestamp.num_milliseconds();
+    let hours = ms / 3_600_000;
+    let minutes = ms / 60_000 % 60;
+    let seconds = ms / 1000 % 60;
+    let millis = ms % 1000;
+    format!("{hours:02}:{minutes:02}:{seconds:02}{separator}{millis:03}")
+}
+
+#[test]
+fn test_format_timestamp() {
+    let timestamp = Duration::milliseconds(3_723_045);
+    assert_eq!(format_timestamp(&timestamp, ','), "01:02:03,045");
+    assert_eq!(format_timestamp(&timestamp, '.'), "01:02:03.045");
+}
+
+fn srt(cues: &[Cue]) -> String {
+    cues.iter()
+        .enumerate()
+        .map(|(i, cue)| {
+            format!(
+                "{}\n{} --> {}\n{}\n",
+                i + 1,
+                format_timestamp(&cue.start, ','),
+                format_timestamp(&cue.end, ','),
+                cue.text
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn vtt(cues: &[Cue]) -> String {
+    let body = cues
+        .iter()
+        .map(|cue| {
+            format!(
+                "{} --> {}\n{}\n",
+                format_timestamp(&cue.start, '.'),
+                format_timestamp(&cue.end, '.'),
+                cue.text
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    format!("WEBVTT\n\n{body}")
+}
+
+/// Write SRT and WebVTT subtitles next to the video.
+///
+/// For example, for `out.mp4` this writes `out.srt` and `out.vtt`.
+pub(crate) fn write_subtitles(
+    dir: &str,
+    slides: &[Slide],
+    durations: &[SlideDuration],
+    output: &str,
+) {
+    let cues = cues(slides, durations);
+    let video_path = Path::new(dir).join(output);
+    let srt_path = video_path.with_extension("srt");
+    std::fs::write(&srt_path, srt(&cues)).unwrap();
+    let vtt_path = video_path.with_extension("vtt");
+    std::fs::write(&vtt_path, vtt(&cues)).unwrap();
+    tracing::info!(
+        "Wrote subtitles to {} and {}",
+        srt_path.display(),
+        vtt_path.display()
+    );
+}
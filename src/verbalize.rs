@@ -0,0 +1,756 @@
+//! Verbalization of numbers, units, dates, and math in speaker notes.
+//!
+//! Voices often read `x^2 + 1` or `3.5e-3` badly, so these are written out
+//! in words right before the text is sent to the provider.
+
+use crate::markup::Markup;
+
+/// Language in which numbers and symbols are written out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Language {
+    English,
+    German,
+}
+
+/// Symbols with their words in English and German.
+///
+/// Longer symbols come first so that `<=` is not read as `<`.
+const SYMBOLS: &[(&str, &str, &str)] = &[
+    ("<=", "is less than or equal to", "kleiner gleich"),
+    (">=", "is greater than or equal to", "größer gleich"),
+    ("!=", "is not equal to", "ungleich"),
+    ("==", "equals", "gleich"),
+    ("+", "plus", "plus"),
+    ("-", "minus", "minus"),
+    ("=", "equals", "gleich"),
+    ("<", "is less than", "kleiner als"),
+    (">", "is greater than", "größer als"),
+    ("*", "times", "mal"),
+    ("/", "divided by", "geteilt durch"),
+    ("−", "minus", "minus"),
+    ("×", "times", "mal"),
+    ("·", "times", "mal"),
+    ("÷", "divided by", "geteilt durch"),
+    ("±", "plus or minus", "plus minus"),
+    ("≈", "is approximately", "ungefähr"),
+    ("≠", "is not equal to", "ungleich"),
+    ("≤", "is less than or equal to", "kleiner gleich"),
+    ("≥", "is greater than or equal to", "größer gleich"),
+    ("π", "pi", "Pi"),
+    ("∞", "infinity", "unendlich"),
+    // LaTeX commands and Typst symbols, which are only read in math.
+    ("\\cdot", "times", "mal"),
+    ("\\times", "times", "mal"),
+    ("\\div", "divided by", "geteilt durch"),
+    ("\\pm", "plus or minus", "plus minus"),
+    ("\\leq", "is less than or equal to", "kleiner gleich"),
+    ("\\geq", "is greater than or equal to", "größer gleich"),
+    ("\\neq", "is not equal to", "ungleich"),
+    ("\\approx", "is approximately", "ungefähr"),
+    ("\\pi", "pi", "Pi"),
+    ("\\infty", "infinity", "unendlich"),
+    ("dot", "times", "mal"),
+    ("times", "times", "mal"),
+    ("div", "divided by", "geteilt durch"),
+    ("approx", "is approximately", "ungefähr"),
+    ("oo", "infinity", "unendlich"),
+];
+
+/// Units that are written out after a number.
+///
+/// Contains the symbol and the singular and plural in English and German.
+#[rustfmt::skip]
+const UNITS: &[(&str, &str, &str, &str, &str)] = &[
+    ("km/h", "one kilometer per hour", "kilometers per hour", "ein Kilometer pro Stunde", "Kilometer pro Stunde"),
+    ("km", "one kilometer", "kilometers", "ein Kilometer", "Kilometer"),
+    ("cm", "one centimeter", "centimeters", "ein Zentimeter", "Zentimeter"),
+    ("m", "one meter", "meters", "ein Meter", "Meter"),
+    ("kg", "one kilogram", "kilograms", "ein Kilogramm", "Kilogramm"),
+    ("g", "one gram", "grams", "ein Gramm", "Gramm"),
+    ("ms", "one millisecond", "milliseconds", "eine Millisekunde", "Millisekunden"),
+    ("s", "one second", "seconds", "eine Sekunde", "Sekunden"),
+    ("MB", "one megabyte", "megabytes", "ein Megabyte", "Megabyte"),
+    ("GB", "one gigabyte", "gigabytes", "ein Gigabyte", "Gigabyte"),
+    ("°C", "one degree Celsius", "degrees Celsius", "ein Grad Celsius", "Grad Celsius"),
+];
+
+/// Words separated by spaces, so that the tables stay short.
+const ENGLISH_ONES: &str = "zero one two three four five six seven eight nine ten eleven twelve \
+    thirteen fourteen fifteen sixteen seventeen eighteen nineteen";
+const ENGLISH_TENS: &str = "twenty thirty forty fifty sixty seventy eighty ninety";
+const ENGLISH_MONTHS: &str = "January February March April May June July August September \
+    October November December";
+const GERMAN_ONES: &str = "null eins zwei drei vier fünf sechs sieben acht neun zehn elf zwölf \
+    dreizehn vierzehn fünfzehn sechzehn siebzehn achtzehn neunzehn";
+const GERMAN_TENS: &str = "zwanzig dreißig vierzig fünfzig sechzig siebzig achtzig neunzig";
+const GERMAN_MONTHS: &str = "Januar Februar März April Mai Juni Juli August September Oktober \
+    November Dezember";
+
+/// The `i`th word of the table.
+fn nth(table: &'static str, i: u64) -> &'static str {
+    table.split_whitespace().nth(i as usize).unwrap()
+}
+
+impl Language {
+    /// Language for a language code such as `en-US`.
+    ///
+    /// Defaults to English when no language code is set.
+    pub(crate) fn from_code(code: Option<&str>) -> Option<Language> {
+        let code = code.unwrap_or("en").to_lowercase();
+        match code.split(['-', '_']).next().unwrap() {
+            "en" => Some(Language::English),
+            "de" => Some(Language::German),
+            _ => None,
+        }
+    }
+
+    fn pick(self, english: &'static str, german: &'static str) -> &'static str {
+        match self {
+            Language::English => english,
+            Language::German => german,
+        }
+    }
+
+    fn decimal_separator(self) -> char {
+        match self {
+            Language::English => '.',
+            Language::German => ',',
+        }
+    }
+
+    fn group_separator(self) -> char {
+        match self {
+            Language::English => ',',
+            Language::German => '.',
+        }
+    }
+
+    /// The number in words, for example, `twenty-one` or `einundzwanzig`.
+    fn cardinal(self, n: u64) -> String {
+        match self {
+            Language::English => english_cardinal(n),
+            Language::German => german_cardinal(n),
+        }
+    }
+
+    /// The ordinal in words, for example, `twenty-first` or `einundzwanzigste`.
+    fn ordinal(self, n: u64) -> String {
+        match self {
+            Language::English => {
+                let cardinal = english_cardinal(n);
+                let split = cardinal.rfind([' ', '-']).map(|i| i + 1).unwrap_or(0);
+                let (head, last) = cardinal.split_at(split);
+                let last = match last {
+                    "one" => "first".to_string(),
+                    "two" => "second".to_string(),
+                    "three" => "third".to_string(),
+                    "five" => "fifth".to_string(),
+                    "eight" => "eighth".to_string(),
+                    "nine" => "ninth".to_string(),
+                    "twelve" => "twelfth".to_string(),
+                    last => match last.strip_suffix('y') {
+                        Some(stem) => format!("{stem}ieth"),
+                        None => format!("{last}th"),
+                    },
+                };
+                format!("{head}{last}")
+            }
+            Language::German => match n {
+                1 => "erste".to_string(),
+                3 => "dritte".to_string(),
+                7 => "siebte".to_string(),
+                8 => "achte".to_string(),
+                n if n < 20 => format!("{}te", german_cardinal(n)),
+                n => format!("{}ste", german_cardinal(n)),
+            },
+        }
+    }
+
+    /// The year of a date in words, for example, `nineteen ninety-nine`.
+    fn year(self, n: u64) -> String {
+        let (high, low) = (n / 100, n % 100);
+        match self {
+            Language::English if (1100..2000).contains(&n) || (2010..2100).contains(&n) => {
+                match low {
+                    0 => format!("{} hundred", english_cardinal(high)),
+                    1..10 => format!("{} oh {}", english_cardinal(high), english_cardinal(low)),
+                    _ => format!("{} {}", english_cardinal(high), english_cardinal(low)),
+                }
+            }
+            Language::German if (1100..2000).contains(&n) => {
+                let low = if low == 0 {
+                    String::new()
+                } else {
+                    german_cardinal(low)
+                };
+                format!("{}hundert{low}", german_cardinal(high))
+            }
+            _ => self.cardinal(n),
+        }
+    }
+
+    /// The date in words.
+    ///
+    /// German dates are read in the dative, as in "am fünfzehnten März".
+    fn date(self, year: u64, month: u64, day: u64) -> String {
+        match self {
+            Language::English => format!(
+                "{} {}, {}",
+                nth(ENGLISH_MONTHS, month - 1),
+                self.ordinal(day),
+                self.year(year)
+            ),
+            Language::German => format!(
+                "{}n {} {}",
+                self.ordinal(day),
+                nth(GERMAN_MONTHS, month - 1),
+                self.year(year)
+            ),
+        }
+    }
+}
+
+fn english_cardinal(n: u64) -> String {
+    const SCALES: [(u64, &str); 4] = [
+        (1_000_000_000_000, "trillion"),
+        (1_000_000_000, "billion"),
+        (1_000_000, "million"),
+        (1_000, "thousand"),
+    ];
+    for (scale, name) in SCALES {
+        if scale <= n {
+            let rest = match n % scale {
+                0 => String::new(),
+                rest => format!(" {}", english_cardinal(rest)),
+            };
+            return format!("{} {name}{rest}", english_cardinal(n / scale));
+        }
+    }
+    match n {
+        0..20 => nth(ENGLISH_ONES, n).to_string(),
+        20..100 => match n % 10 {
+            0 => nth(ENGLISH_TENS, n / 10 - 2).to_string(),
+            ones => format!(
+                "{}-{}",
+                nth(ENGLISH_TENS, n / 10 - 2),
+                nth(ENGLISH_ONES, ones)
+            ),
+        },
+        _ => match n % 100 {
+            0 => format!("{} hundred", nth(ENGLISH_ONES, n / 100)),
+            rest => format!(
+                "{} hundred {}",
+                nth(ENGLISH_ONES, n / 100),
+                english_cardinal(rest)
+            ),
+        },
+    }
+}
+
+/// The German number as used in compounds, for example, `ein` in
+/// `einhundert`.
+fn german_prefix(n: u64) -> String {
+    let cardinal = german_cardinal(n);
+    match cardinal.strip_suffix("eins") {
+        Some(stem) => format!("{stem}ein"),
+        None => cardinal,
+    }
+}
+
+fn german_cardinal(n: u64) -> String {
+    const SCALES: [(u64, &str, &str); 3] = [
+        (1_000_000_000_000, "Billion", "Billionen"),
+        (1_000_000_000, "Milliarde", "Milliarden"),
+        (1_000_000, "Million", "Millionen"),
+    ];
+    for (scale, one, many) in SCALES {
+        if scale <= n {
+            let count = match n / scale {
+                1 => format!("eine {one}"),
+                count => format!("{} {many}", german_cardinal(count)),
+            };
+            return match n % scale {
+                0 => count,
+                rest => format!("{count} {}", german_cardinal(rest)),
+            };
+        }
+    }
+    let rest = |rest: u64| match rest {
+        0 => String::new(),
+        rest => german_cardinal(rest),
+    };
+    match n {
+        0..20 => nth(GERMAN_ONES, n).to_string(),
+        20..100 => match n % 10 {
+            0 => nth(GERMAN_TENS, n / 10 - 2).to_string(),
+            ones => format!("{}und{}", german_prefix(ones), nth(GERMAN_TENS, n / 10 - 2)),
+        },
+        100..1000 => format!("{}hundert{}", german_prefix(n / 100), rest(n % 100)),
+        _ => format!("{}tausend{}", german_prefix(n / 1000), rest(n % 1000)),
+    }
+}
+
+#[test]
+fn test_cardinal() {
+    let en = Language::English;
+    assert_eq!(en.cardinal(0), "zero");
+    assert_eq!(en.cardinal(21), "twenty-one");
+    assert_eq!(en.cardinal(105), "one hundred five");
+    assert_eq!(
+        en.cardinal(1_234_567),
+        "one million two hundred thirty-four thousand five hundred sixty-seven"
+    );
+    assert_eq!(en.ordinal(21), "twenty-first");
+    assert_eq!(en.ordinal(40), "fortieth");
+    assert_eq!(en.year(1999), "nineteen ninety-nine");
+    assert_eq!(en.year(2005), "two thousand five");
+
+    let de = Language::German;
+    assert_eq!(de.cardinal(21), "einundzwanzig");
+    assert_eq!(de.cardinal(101_000), "einhunderteintausend");
+    assert_eq!(de.cardinal(2_000_001), "zwei Millionen eins");
+    assert_eq!(de.ordinal(3), "dritte");
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether the characters at `i` are `text`.
+fn starts_with(chars: &[char], i: usize, text: &str) -> bool {
+    let len = text.chars().count();
+    chars
+        .get(i..i + len)
+        .is_some_and(|found| found.iter().copied().eq(text.chars()))
+}
+
+/// Number of digits at `i`.
+fn digits(chars: &[char], i: usize) -> usize {
+    chars[i.min(chars.len())..]
+        .iter()
+        .take_while(|c| c.is_ascii_digit())
+        .count()
+}
+
+fn number_at(chars: &[char], i: usize, len: usize) -> u64 {
+    chars[i..i + len]
+        .iter()
+        .collect::<String>()
+        .parse()
+        .unwrap()
+}
+
+/// Read the digits one by one, for example, after the decimal separator.
+fn digit_words(language: Language, digits: &[char]) -> String {
+    digits
+        .iter()
+        .map(|c| language.cardinal(c.to_digit(10).unwrap() as u64))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Scan a date such as `2024-03-15` (or `15.03.2024` in German) at `i`.
+fn scan_date(chars: &[char], i: usize, language: Language) -> Option<(String, usize)> {
+    let at = |j: usize| chars.get(j).copied();
+    let (year, month, day, end) = if digits(chars, i) == 4
+        && at(i + 4) == Some('-')
+        && digits(chars, i + 5) == 2
+        && at(i + 7) == Some('-')
+        && digits(chars, i + 8) == 2
+    {
+        let year = number_at(chars, i, 4);
+        (
+            year,
+            number_at(chars, i + 5, 2),
+            number_at(chars, i + 8, 2),
+            i + 10,
+        )
+    } else if language == Language::German
+        && digits(chars, i) == 2
+        && at(i + 2) == Some('.')
+        && digits(chars, i + 3) == 2
+        && at(i + 5) == Some('.')
+        && digits(chars, i + 6) == 4
+    {
+        let day = number_at(chars, i, 2);
+        (
+            number_at(chars, i + 6, 4),
+            number_at(chars, i + 3, 2),
+            day,
+            i + 10,
+        )
+    } else {
+        return None;
+    };
+    let valid = (1..=12).contains(&month) && (1..=31).contains(&day);
+    (valid && !at(end).is_some_and(|c| c.is_ascii_digit()))
+        .then(|| (language.date(year, month, day), end))
+}
+
+/// Scan a number with optional sign, unit, or percent sign at `i`.
+///
+/// Returns the words and the index after the number, or `None` if the
+/// number is part of a word such as `64bit`. In math, letters may follow
+/// the number, as in `2x`.
+fn scan_number(
+    chars: &[char],
+    i: usize,
+    language: Language,
+    math: bool,
+) -> Option<(String, usize)> {
+    let at = |j: usize| chars.get(j).copied();
+    let negative = matches!(at(i), Some('-' | '−'));
+    let start = i + negative as usize;
+    if !negative {
+        if let Some(date) = scan_date(chars, i, language) {
+            return Some(date);
+        }
+    }
+    let mut j = start + digits(chars, start);
+    let mut integer = chars[start..j].to_vec();
+    while at(j) == Some(language.group_separator())
+        && digits(chars, j + 1) == 3
+        && !at(j + 4).is_some_and(|c| c.is_ascii_digit())
+    {
+        integer.extend(&chars[j + 1..j + 4]);
+        j += 4;
+    }
+    let minus = language.pick("minus", "minus");
+    let mut words = Vec::new();
+    if negative {
+        words.push(minus.to_string());
+    }
+    let value =
+        (integer.len() <= 15).then(|| integer.iter().collect::<String>().parse::<u64>().unwrap());
+    match value {
+        Some(value) => words.push(language.cardinal(value)),
+        None => words.push(digit_words(language, &integer)),
+    }
+    let mut one = !negative && value == Some(1);
+    if at(j) == Some(language.decimal_separator()) && 0 < digits(chars, j + 1) {
+        let len = digits(chars, j + 1);
+        words.push(language.pick("point", "Komma").to_string());
+        words.push(digit_words(language, &chars[j + 1..j + 1 + len]));
+        j += 1 + len;
+        one = false;
+    }
+    if matches!(at(j), Some('e' | 'E')) {
+        let sign = matches!(at(j + 1), Some('-' | '+'));
+        let k = j + 1 + sign as usize;
+        let len = digits(chars, k);
+        // Longer exponents are left as they are.
+        if (1..=15).contains(&len) && !at(k + len).is_some_and(is_word_char) {
+            let power = language.pick("times ten to the power of", "mal zehn hoch");
+            words.push(power.to_string());
+            if at(j + 1) == Some('-') {
+                words.push(minus.to_string());
+            }
+            words.push(language.cardinal(number_at(chars, k, len)));
+            j = k + len;
+            one = false;
+        }
+    }
+    // Versions such as `1.2.3`, times such as `3:30`, and ranges such as
+    // `5-10` are left as they are instead of being read in parts.
+    let separator = matches!(at(j), Some('.' | ',' | ':' | '-' | '–' | '/'));
+    if !math && separator && at(j + 1).is_some_and(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    if at(j) == Some('%') {
+        words.push(language.pick("percent", "Prozent").to_string());
+        return Some((words.join(" "), j + 1));
+    }
+    let k = j + (at(j) == Some(' ')) as usize;
+    for (symbol, english_one, english, german_one, german) in UNITS {
+        let end = k + symbol.chars().count();
+        if starts_with(chars, k, symbol) && !at(end).is_some_and(is_word_char) {
+            if one {
+                words.clear();
+            }
+            let unit = match one {
+                true => language.pick(english_one, german_one),
+                false => language.pick(english, german),
+            };
+            words.push(unit.to_string());
+            return Some((words.join(" "), end));
+        }
+    }
+    if !math && at(j).is_some_and(is_word_char) {
+        return None;
+    }
+    Some((words.join(" "), j))
+}
+
+/// Scan a symbol at `i` that is read as a word.
+///
+/// Outside of math, ASCII operators are only read when they are surrounded
+/// by spaces, so that `well-known` and `and/or` are kept, and a spaced `-`
+/// is kept since it is usually a dash.
+fn scan_symbol(chars: &[char], i: usize, language: Language, math: bool) -> Option<(&str, usize)> {
+    let before = i.checked_sub(1).map(|k| chars[k]);
+    SYMBOLS.iter().find_map(|(symbol, english, german)| {
+        let end = i + symbol.chars().count();
+        if !starts_with(chars, i, symbol) {
+            return None;
+        }
+        let after = chars.get(end).copied();
+        let first = symbol.chars().next().unwrap();
+        let spaced =
+            before.is_none_or(char::is_whitespace) && after.is_none_or(char::is_whitespace);
+        let read = if first == '\\' {
+            math && !after.is_some_and(char::is_alphabetic)
+        } else if first.is_alphabetic() {
+            math && !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+        } else if first.is_ascii() {
+            math || (spaced && *symbol != "-")
+        } else {
+            true
+        };
+        read.then(|| (language.pick(english, german), end))
+    })
+}
+
+/// End of inline math such as `$x^2$` that starts at `i`.
+///
+/// Like in Pandoc, the opening `$` has to be followed by a non-space and the
+/// closing `$` has to follow a non-space, so that prices such as `$5 and $10`
+/// are not read as math. Typst math with spaces on both sides, such as
+/// `$ x^2 $`, is also supported.
+fn math_end(chars: &[char], i: usize) -> Option<usize> {
+    let end = i + 1 + chars[i + 1..].iter().position(|c| *c == '$')?;
+    if end == i + 1 {
+        return None;
+    }
+    let first = chars[i + 1];
+    let last = chars[end - 1];
+    let after = chars.get(end + 1);
+    let inline = !first.is_whitespace()
+        && !last.is_whitespace()
+        && !after.is_some_and(|c| c.is_ascii_digit());
+    let display = first == ' ' && last == ' ';
+    (inline || display).then_some(end)
+}
+
+/// Extract a group such as `{x}` or `(x)` at `i`.
+///
+/// Returns the content and the index after the group.
+fn group(chars: &[char], i: usize, open: char, close: char) -> Option<(String, usize)> {
+    if chars.get(i) != Some(&open) {
+        return None;
+    }
+    let mut depth = 0;
+    for (j, c) in chars.iter().enumerate().skip(i) {
+        if *c == open {
+            depth += 1;
+        } else if *c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some((chars[i + 1..j].iter().collect(), j + 1));
+            }
+        }
+    }
+    None
+}
+
+/// Rewrite fractions and square roots, such as `\frac{a}{b}` or
+/// `frac(a, b)`, into words.
+fn rewrite_math(text: &str, language: Language) -> String {
+    let chars = text.chars().collect::<Vec<char>>();
+    let over = language.pick("over", "durch");
+    let root = language.pick("the square root of", "die Wurzel aus");
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let at_boundary = i == 0 || !chars[i - 1].is_alphanumeric();
+        if starts_with(&chars, i, "\\frac") || (at_boundary && starts_with(&chars, i, "frac")) {
+            let start = i + if chars[i] == '\\' { 5 } else { 4 };
+            let args = match group(&chars, start, '{', '}') {
+                Some((a, j)) => group(&chars, j, '{', '}').map(|(b, k)| (a, b, k)),
+                None => group(&chars, start, '(', ')').and_then(|(args, j)| {
+                    let (a, b) = args.split_once(',')?;
+                    Some((a.to_string(), b.to_string(), j))
+                }),
+            };
+            if let Some((a, b, j)) = args {
+                let a = rewrite_math(&a, language);
+                let b = rewrite_math(&b, language);
+                out.push_str(&format!(" {a} {over} {b} "));
+                i = j;
+                continue;
+            }
+        }
+        if starts_with(&chars, i, "\\sqrt") || (at_boundary && starts_with(&chars, i, "sqrt")) {
+            let start = i + if chars[i] == '\\' { 5 } else { 4 };
+            let arg = group(&chars, start, '{', '}').or_else(|| group(&chars, start, '(', ')'));
+            if let Some((a, j)) = arg {
+                out.push_str(&format!(" {root} {} ", rewrite_math(&a, language)));
+                i = j;
+                continue;
+            }
+        }
+        out.push(chars[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Append the words, separated by a space from the preceding word.
+fn push_words(out: &mut String, words: &str) {
+    if out.ends_with(char::is_alphanumeric) {
+        out.push(' ');
+    }
+    out.push_str(words);
+}
+
+fn verbalize_text(text: &str, language: Language, math: bool) -> String {
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut out = String::new();
+    // Whether a space is needed before the next word.
+    let mut after_words = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let prev = i.checked_sub(1).map(|k| chars[k]);
+        let next = chars.get(i + 1).copied();
+        let mut words = None;
+        if c == '$' && !math {
+            if let Some(end) = math_end(&chars, i) {
+                let inner = chars[i + 1..end].iter().collect::<String>();
+                words = Some((verbalize_math(inner.trim(), language), end + 1));
+            }
+        }
+        let unary = matches!(c, '-' | '−')
+            && prev.is_none_or(|p| p.is_whitespace() || p == '=' || p == '(')
+            && next.is_some_and(|n| n.is_ascii_digit());
+        // Amounts such as `$5` are left as they are, as are the parts of
+        // versions, times, and ranges that follow a separator.
+        let kept = |p: char| match math {
+            true => p == '.',
+            false => matches!(p, '.' | ',' | ':' | '-' | '–' | '/' | '$' | '€' | '£'),
+        };
+        let number = c.is_ascii_digit() && !prev.is_some_and(|p| is_word_char(p) || kept(p));
+        if words.is_none() && (unary || number) {
+            words = scan_number(&chars, i, language, math);
+        }
+        if words.is_none() && c == '^' && (math || prev.is_some_and(is_word_char)) {
+            // Math may contain spaces from removed braces, as in `x^{2}`.
+            let k = i
+                + 1
+                + chars[i + 1..]
+                    .iter()
+                    .take_while(|c| c.is_whitespace())
+                    .count();
+            let single = !chars.get(k + 1).is_some_and(|c| c.is_ascii_digit());
+            words = Some(match chars.get(k) {
+                Some('2') if single => (language.pick("squared", "Quadrat").to_string(), k + 1),
+                Some('3') if single => (language.pick("cubed", "hoch drei").to_string(), k + 1),
+                _ => (language.pick("to the power of", "hoch").to_string(), i + 1),
+            });
+        }
+        if words.is_none() {
+            words =
+                scan_symbol(&chars, i, language, math).map(|(word, end)| (word.to_string(), end));
+        }
+        match words {
+            Some((words, end)) => {
+                push_words(&mut out, &words);
+                after_words = true;
+                i = end;
+            }
+            None => {
+                if after_words && c.is_alphanumeric() {
+                    out.push(' ');
+                }
+                after_words = false;
+                // Unknown LaTeX commands such as `\alpha` are read by name.
+                if !(math && c == '\\') {
+                    out.push(c);
+                }
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn verbalize_math(text: &str, language: Language) -> String {
+    let text = rewrite_math(text, language).replace(['_', '{', '}', '(', ')'], " ");
+    let text = verbalize_text(&text, language, true);
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Write out numbers, units, dates, math, and common symbols in words.
+///
+/// Math between dollar signs, such as `$x^2 + 1$`, is read as math.
+pub(crate) fn verbalize(text: &str, language: Language) -> String {
+    verbalize_text(text, language, false)
+}
+
+/// Verbalize the text parts of the markup.
+pub(crate) fn verbalize_markup(parts: Vec<Markup>, language: Language) -> Vec<Markup> {
+    parts
+        .into_iter()
+        .map(|part| match part {
+            Markup::Text(text) => Markup::Text(verbalize(&text, language)),
+            Markup::Slow(text) => Markup::Slow(verbalize(&text, language)),
+            Markup::Emphasis(text) => Markup::Emphasis(verbalize(&text, language)),
+            Markup::Pause(duration) => Markup::Pause(duration),
+        })
+        .collect()
+}
+
+#[test]
+fn test_verbalize() {
+    let en = |text: &str| verbalize(text, Language::English);
+    assert_eq!(en("2 + 2 = 4."), "two plus two equals four.");
+    assert_eq!(en("x^2 + 1"), "x squared plus one");
+    assert_eq!(
+        en("It is 3.5e-3 or -1.25."),
+        "It is three point five times ten to the power of minus three or minus one point two five."
+    );
+    assert_eq!(
+        en("Drive 1 km at 50 km/h."),
+        "Drive one kilometer at fifty kilometers per hour."
+    );
+    assert_eq!(
+        en("About 50% of 1,000 people (in 2024)."),
+        "About fifty percent of one thousand people (in two thousand twenty-four)."
+    );
+    assert_eq!(
+        en("On 2024-03-15."),
+        "On March fifteenth, twenty twenty-four."
+    );
+    assert_eq!(
+        en("The well-known and/or mp3 - 64bit (-2)."),
+        "The well-known and/or mp3 - 64bit (minus two)."
+    );
+    for kept in [
+        "5-10",
+        "1.2.3",
+        "3:30",
+        "1/2",
+        "$5",
+        "€1,50",
+        "1e99999999999999999999",
+    ] {
+        assert_eq!(en(kept), kept);
+    }
+    assert_eq!(
+        en("Solve $\\frac{a}{2} = \\sqrt{x_1}$ for $5 and $10."),
+        "Solve a over two equals the square root of x one for $5 and $10."
+    );
+    assert_eq!(
+        en("Use $frac(1, n) <= pi$ and $2x^{3} \\cdot \\alpha$."),
+        "Use one over n is less than or equal to pi and two x cubed times alpha."
+    );
+
+    let de = |text: &str| verbalize(text, Language::German);
+    assert_eq!(
+        de("Am 15.03.2024 waren es 3,5 km."),
+        "Am fünfzehnten März zweitausendvierundzwanzig waren es drei Komma fünf Kilometer."
+    );
+    assert_eq!(
+        de("1 s und 21 s"),
+        "eine Sekunde und einundzwanzig Sekunden"
+    );
+}
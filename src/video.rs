@@ -1,12 +1,20 @@
 use crate::audio_format;
+use crate::captions::caption_filters;
+use crate::captions::CaptionsConfig;
 use crate::path::audio_path;
 use crate::path::image_path;
+use crate::path::segment_path;
+use crate::provider::TtsProvider;
 use crate::slide::Slide;
 use crate::Config;
 use crate::Provider;
 use chrono::NaiveTime;
 use chrono::SubsecRound;
 use chrono::Timelike;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
 use std::path::Path;
 use std::path::PathBuf;
 
@@ -15,6 +23,17 @@ use std::path::PathBuf;
 // Since the video consists of images, data-wise it should be not a problem to go for a higher resolution.
 const HEIGHT: i32 = 1920;
 
+/// Frame rate of the video.
+///
+/// Most video players don't like low frame rates and the transitions need
+/// enough frames to look smooth.
+const FRAMERATE: i64 = 30;
+
+/// Sample rate of the audio in the segments.
+///
+/// At this rate, each frame holds a whole number of samples.
+const SAMPLE_RATE: i64 = 48000;
+
 /// Parse the duration from the output of `ffprobe`.
 ///
 /// See https://ffmpeg.org/ffmpeg-utils.html#time-duration-syntax.
@@ -54,7 +73,7 @@ fn test_parse_ffprobe_duration() {
     );
 }
 
-fn probe_duration(path: &PathBuf) -> Option<NaiveTime> {
+pub(crate) fn probe_duration(path: &PathBuf) -> Option<NaiveTime> {
     let output = std::process::Command::new("ffprobe")
         .arg("-i")
         .arg(path)
@@ -73,6 +92,9 @@ fn probe_duration(path: &PathBuf) -> Option<NaiveTime> {
         .split(",")
         .next()
         .unwrap();
+    if duration == "N/A" {
+        return None;
+    }
     Some(parse_ffmpeg_duration(duration))
 }
 
@@ -101,48 +123,127 @@ fn test_print_ffmpeg_duration() {
     );
 }
 
-fn video_output_name(slide: &Slide) -> String {
-    // For example, the first video will be called v1, the second v2, etc.
-    format!("v{}", slide.idx)
+fn seconds(duration: &chrono::Duration) -> f64 {
+    duration.num_milliseconds() as f64 / 1000.0
 }
 
-fn video_filters(slides: &[Slide]) -> Vec<String> {
-    slides
-        .iter()
-        .map(|slide| {
-            let input_index = stream_index(slide, Stream::Video);
-            let output_name = video_output_name(slide);
-            // For example, `[1:v]scale=-1:1920,format=yuv420p[v1];`.
-            format!("[{input_index}:v]scale=-1:{HEIGHT},format=yuv420p[{output_name}];")
-        })
-        .collect::<Vec<String>>()
+/// Length of each segment in frames.
+///
+/// The segment of a slide includes the transition into the slide and ends
+/// where the transition into the next slide starts. Segments have to consist of
+/// whole frames to be joined without gaps. The boundaries between the segments
+/// are rounded to the nearest frame, so that the rounding errors do not add up.
+fn segment_frames(durations: &[SlideDuration]) -> Vec<i64> {
+    let mut end = chrono::Duration::zero();
+    let mut previous_frame = 0;
+    let mut frames = Vec::new();
+    for (i, duration) in durations.iter().enumerate() {
+        let next_transition = durations
+            .get(i + 1)
+            .map(|next| next.transition)
+            .unwrap_or_else(chrono::Duration::zero);
+        end += duration.total() - next_transition;
+        let frame = (end.num_milliseconds() * FRAMERATE + 500) / 1000;
+        frames.push(frame - previous_frame);
+        previous_frame = frame;
+    }
+    frames
 }
 
-fn video_inputs(slides: &[Slide]) -> Vec<String> {
-    slides
-        .iter()
-        .map(|slide| {
-            let video_output_name = video_output_name(slide);
-            let audio_index = stream_index(slide, Stream::Audio);
-            // For example, `[v1][0:a]`.
-            format!("[{video_output_name}][{audio_index}:a]")
-        })
-        .collect::<Vec<String>>()
+#[test]
+fn test_segment_frames() {
+    let duration = |transition, audio| SlideDuration {
+        transition: chrono::Duration::milliseconds(transition),
+        audio: chrono::Duration::milliseconds(audio),
+        pause: chrono::Duration::milliseconds(510),
+    };
+    let durations = vec![duration(0, 1000), duration(500, 1000), duration(0, 1000)];
+    // The segments end at 1.01 s, 3.02 s, and 4.53 s.
+    assert_eq!(segment_frames(&durations), vec![30, 61, 45]);
+}
+
+/// Filter graph that encodes one slide into a segment.
+///
+/// The inputs are the image of the previous slide (only when the slide has a
+/// transition), the image of the slide, and the audio of the slide. The
+/// transition into the slide is part of the segment so that the segments can
+/// be joined without re-encoding. The audio is delayed by the transition and
+/// padded to the length of the segment.
+fn segment_filter(
+    transition: Transition,
+    duration: &SlideDuration,
+    frames: i64,
+    captions: &str,
+) -> String {
+    let scale = format!("scale=-1:{HEIGHT},format=yuv420p,fps={FRAMERATE}");
+    let delay = duration.transition.num_milliseconds();
+    let samples = frames * SAMPLE_RATE / FRAMERATE;
+    // For example, `adelay=500:all=1,aresample=48000,apad=whole_len=96000,atrim=end_sample=96000`.
+    let audio = format!(
+        "adelay={delay}:all=1,aresample={SAMPLE_RATE},apad=whole_len={samples},atrim=end_sample={samples}"
+    );
+    match transition.xfade_name() {
+        Some(name) => {
+            let d = seconds(&duration.transition);
+            [
+                format!("[0:v]{scale}[previous];"),
+                format!("[1:v]{scale}{captions}[current];"),
+                format!("[previous][current]xfade=transition={name}:duration={d}:offset=0[outv];"),
+                format!("[2:a]{audio}[outa]"),
+            ]
+            .join(" ")
+        }
+        None => format!("[0:v]{scale}{captions}[outv]; [1:a]{audio}[outa]"),
+    }
+}
+
+#[test]
+fn test_segment_filter() {
+    let duration = SlideDuration {
+        transition: chrono::Duration::milliseconds(500),
+        audio: chrono::Duration::milliseconds(1000),
+        pause: chrono::Duration::milliseconds(500),
+    };
+    let filter = segment_filter(Transition::Fade, &duration, 60, "");
+    let expected = [
+        "[0:v]scale=-1:1920,format=yuv420p,fps=30[previous];",
+        "[1:v]scale=-1:1920,format=yuv420p,fps=30[current];",
+        "[previous][current]xfade=transition=fade:duration=0.5:offset=0[outv];",
+        "[2:a]adelay=500:all=1,aresample=48000,apad=whole_len=96000,atrim=end_sample=96000[outa]",
+    ];
+    assert_eq!(filter, expected.join(" "));
 }
 
-enum Stream {
-    Audio,
-    Video,
+/// Hash of the content of the file.
+fn file_hash(path: &Path) -> String {
+    let content = std::fs::read(path).unwrap();
+    format!("{:x}", Sha256::digest(content))
 }
 
-fn stream_index(slide: &Slide, stream: Stream) -> usize {
-    // For example, the first stream will be at 0, the second at 2, etc.
-    let index = 2 * (slide.idx - 1);
-    match stream {
-        // For example, the first audio is at index 0, the second at index 2, etc.
-        Stream::Audio => index,
-        // For example, the first image is at index 1:v, the second at index 3:v, etc.
-        Stream::Video => index + 1,
+/// Key of the video segment of a slide.
+///
+/// A segment is only encoded again when one of its inputs or the encode
+/// settings change.
+#[derive(Serialize)]
+struct SegmentKey<'a> {
+    image: String,
+    /// Image of the previous slide, which is part of the transition.
+    previous_image: Option<String>,
+    audio: String,
+    transition: Transition,
+    transition_ms: i64,
+    audio_ms: i64,
+    frames: i64,
+    /// Speaker note and style of the burned captions.
+    captions: Option<(&'a str, &'a CaptionsConfig)>,
+    height: i32,
+    framerate: i64,
+}
+
+impl SegmentKey<'_> {
+    fn hash(&self) -> String {
+        let key = serde_json::to_string(self).unwrap();
+        format!("{:x}", Sha256::digest(key.as_bytes()))
     }
 }
 
@@ -152,7 +253,7 @@ fn stream_index(slide: &Slide, stream: Stream) -> usize {
 /// sentences around slide transitions will be too close to each other.
 /// According to Goldman-Eisler (1968), articulatory pauses are typically
 /// below 250 ms while hesitation pauses are typically above that.
-fn transition_pause(config: &Config, provider: &Provider) -> chrono::Duration {
+fn transition_pause(config: &Config, provider: &TtsProvider) -> chrono::Duration {
     // Google does not automatically have a pause between audio clips.
     if provider == &Provider::Google {
         return chrono::Duration::milliseconds(200);
@@ -175,68 +276,278 @@ fn transition_pause(config: &Config, provider: &Provider) -> chrono::Duration {
     chrono::Duration::milliseconds(50)
 }
 
-pub(crate) fn combine_video(
+/// Transition into a slide.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transition {
+    /// Hard cut.
+    #[default]
+    None,
+    /// The previous slide blends into the next slide.
+    #[serde(alias = "crossfade")]
+    Fade,
+    /// The next slide pushes the previous slide out to the left.
+    SlideLeft,
+    /// The previous slide fades to black and the next slide fades in from black.
+    FadeThroughBlack,
+}
+
+impl Transition {
+    /// Name of the transition in the ffmpeg `xfade` filter.
+    fn xfade_name(&self) -> Option<&'static str> {
+        match self {
+            Transition::None => None,
+            Transition::Fade => Some("fade"),
+            Transition::SlideLeft => Some("slideleft"),
+            Transition::FadeThroughBlack => Some("fadeblack"),
+        }
+    }
+}
+
+/// Transition into the slide at position `i`.
+///
+/// The first slide has no transition since there is no previous slide.
+fn slide_transition(config: &Config, slides: &[Slide], i: usize) -> Transition {
+    if i == 0 {
+        return Transition::None;
+    }
+    let slide = &slides[i];
+    slide
+        .config
+        .transition
+        .or(config.transition)
+        .unwrap_or_default()
+}
+
+fn transition_duration(config: &Config, transition: Transition) -> chrono::Duration {
+    if transition == Transition::None {
+        return chrono::Duration::zero();
+    }
+    let seconds = config.transition_duration.unwrap_or(0.5);
+    chrono::Duration::milliseconds((seconds * 1000.0) as i64)
+}
+
+/// Duration of a slide in the video.
+#[derive(Clone, Debug)]
+pub(crate) struct SlideDuration {
+    /// Duration of the transition into the slide.
+    ///
+    /// During the transition, the slide overlaps with the previous slide. The
+    /// audio of the slide starts after the transition.
+    pub transition: chrono::Duration,
+    /// Duration of the audio of the slide.
+    pub audio: chrono::Duration,
+    /// Pause after the audio.
+    ///
+    /// This is at least as long as the transition into the next slide so that
+    /// the narration is not clipped by the transition.
+    pub pause: chrono::Duration,
+}
+
+impl SlideDuration {
+    pub fn total(&self) -> chrono::Duration {
+        self.transition + self.audio + self.pause
+    }
+}
+
+/// Probe the duration of each slide.
+pub(crate) fn slide_durations(
     dir: &str,
     slides: &[Slide],
     config: &Config,
-    provider: &Provider,
-    output: &str,
-    audio_codec: &str,
-) {
+    provider: &TtsProvider,
+) -> Vec<SlideDuration> {
     let audio_ext = audio_format(config);
-    tracing::info!("Combining images and audio into one video...");
-    let output = Path::new(dir).join(output);
-    let output_path = output.to_str().unwrap();
+    let n = slides.len();
+    slides
+        .iter()
+        .enumerate()
+        .map(|(i, slide)| {
+            let audio_path = audio_path(dir, slide, &audio_ext);
+            let pause = if i < n - 1 {
+                let next = slide_transition(config, slides, i + 1);
+                transition_pause(config, provider).max(transition_duration(config, next))
+            } else {
+                // Sometimes the audio is trimmed at the end. Adding a small pause
+                // to avoid this.
+                chrono::Duration::milliseconds(500)
+            };
+            let transition = transition_duration(config, slide_transition(config, slides, i));
+            let audio = probe_duration(&audio_path).unwrap() - NaiveTime::MIN;
+            SlideDuration {
+                transition,
+                audio,
+                pause,
+            }
+        })
+        .collect()
+}
+
+/// Encode the slide at position `i` into a segment.
+///
+/// Segments are stored by the hash of their key, so the segment is reused when
+/// nothing changed since the last build. Returns the path to the segment.
+fn encode_segment(
+    dir: &str,
+    slides: &[Slide],
+    durations: &[SlideDuration],
+    frames: i64,
+    i: usize,
+    config: &Config,
+    burn_captions: bool,
+) -> PathBuf {
+    let slide = &slides[i];
+    let duration = &durations[i];
+    let audio_path = audio_path(dir, slide, &audio_format(config));
+    let transition = slide_transition(config, slides, i);
+    let previous_image = match transition {
+        Transition::None => None,
+        _ => Some(image_path(dir, &slides[i - 1])),
+    };
+    let image_path = image_path(dir, slide);
+    let key = SegmentKey {
+        image: file_hash(&image_path),
+        previous_image: previous_image.as_deref().map(file_hash),
+        audio: file_hash(&audio_path),
+        transition,
+        transition_ms: duration.transition.num_milliseconds(),
+        audio_ms: duration.audio.num_milliseconds(),
+        frames,
+        captions: burn_captions.then_some((slide.speaker_note.as_str(), &config.captions)),
+        height: HEIGHT,
+        framerate: FRAMERATE,
+    };
+    let segment_path = segment_path(dir, &key.hash());
+    if segment_path.exists() {
+        tracing::info!("Slide {}: Reusing video segment", slide.idx);
+        return segment_path;
+    }
+    tracing::info!("Slide {}: Encoding video segment", slide.idx);
+    // Encode to a temporary file so that an interrupted encode is never
+    // reused as a finished segment. Leftovers are removed with the unused
+    // segments since they also end in `.mkv`.
+    let unfinished = segment_path.with_extension("tmp.mkv");
 
     let mut cmd = std::process::Command::new("ffmpeg");
     cmd.arg("-y");
-    let n = slides.len();
-    for (i, slide) in slides.iter().enumerate() {
-        let audio_path = audio_path(dir, slide, &audio_ext);
-        cmd.arg("-i").arg(&audio_path);
-        let image_path = image_path(dir, slide);
-        let pause = if i < n - 1 {
-            transition_pause(config, provider)
-        } else {
-            // Sometimes the audio is trimmed at the end. Adding a small pause
-            // to avoid this.
-            chrono::Duration::milliseconds(500)
-        };
-        let duration = probe_duration(&audio_path).unwrap() + pause;
+    if let Some(previous_image) = &previous_image {
         cmd.arg("-loop")
             .arg("1")
             .arg("-framerate")
-            .arg("1")
+            .arg(FRAMERATE.to_string())
             .arg("-t")
-            .arg(print_ffmpeg_duration(&duration))
+            .arg(print_ffmpeg_duration(
+                &(NaiveTime::MIN + duration.transition),
+            ))
             .arg("-i")
-            .arg(image_path);
+            .arg(previous_image);
     }
-    let filter = format!(
-        "{} {} concat=n={}:v=1:a=1 [outv] [outa]",
-        video_filters(slides).join(" "),
-        video_inputs(slides).join(""),
-        slides.len()
-    );
+    // The length of the segment is set via `-frames:v` and the audio filters.
+    cmd.arg("-loop")
+        .arg("1")
+        .arg("-framerate")
+        .arg(FRAMERATE.to_string())
+        .arg("-i")
+        .arg(&image_path)
+        .arg("-i")
+        .arg(&audio_path);
+    let captions = if burn_captions {
+        caption_filters(dir, slide, duration, &config.captions)
+    } else {
+        "".to_string()
+    };
     cmd.arg("-filter_complex")
-        .arg(filter)
+        .arg(segment_filter(transition, duration, frames, &captions))
         .arg("-map")
         .arg("[outv]")
-        // Re-encode to 30 fps since most video players don't like 1 fps.
-        .arg("-r")
-        .arg("30")
         .arg("-map")
         .arg("[outa]")
+        .arg("-frames:v")
+        .arg(frames.to_string())
         .arg("-tune")
         .arg("stillimage")
+        // Uncompressed audio has no padding at the start and end, which would
+        // cause small gaps between the segments. The audio is encoded when
+        // joining the segments.
+        .arg("-c:a")
+        .arg("pcm_s16le")
+        .arg(&unfinished);
+    tracing::debug!("FFmpeg command:\n{:?}", cmd);
+    let output = cmd.output().expect("Failed to run ffmpeg command");
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        tracing::error!(
+            "Slide {}: Failed to encode video segment: {stderr}",
+            slide.idx
+        );
+        std::process::exit(1);
+    }
+    std::fs::rename(&unfinished, &segment_path).unwrap();
+    segment_path
+}
+
+/// Remove the segments that are not part of the video anymore.
+fn remove_unused_segments(dir: &str, segments: &[PathBuf]) {
+    let segment_dir = Path::new(dir).join("video");
+    for file in std::fs::read_dir(segment_dir).unwrap() {
+        let path = file.unwrap().path();
+        if path.extension().unwrap_or_default() == "mkv" && !segments.contains(&path) {
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
+
+pub(crate) fn combine_video(
+    dir: &str,
+    slides: &[Slide],
+    durations: &[SlideDuration],
+    config: &Config,
+    output: &str,
+    audio_codec: &str,
+    burn_captions: bool,
+) {
+    tracing::info!("Combining images and audio into one video...");
+    let segment_dir = Path::new(dir).join("video");
+    if !segment_dir.exists() {
+        std::fs::create_dir_all(&segment_dir).unwrap();
+    }
+    let segments = segment_frames(durations)
+        .into_iter()
+        .enumerate()
+        .map(|(i, frames)| encode_segment(dir, slides, durations, frames, i, config, burn_captions))
+        .collect::<Vec<PathBuf>>();
+    remove_unused_segments(dir, &segments);
+
+    // The paths in the list are relative to the list.
+    let list = segments
+        .iter()
+        .map(|path| {
+            let filename = path.file_name().unwrap().to_str().unwrap();
+            format!("file '{filename}'")
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    let list_path = segment_dir.join("segments.txt");
+    std::fs::write(&list_path, list).unwrap();
+
+    let output = Path::new(dir).join(output);
+    let output_path = output.to_str().unwrap();
+    let mut cmd = std::process::Command::new("ffmpeg");
+    cmd.arg("-y")
+        .arg("-f")
+        .arg("concat")
+        .arg("-safe")
+        .arg("0")
+        .arg("-i")
+        .arg(&list_path)
+        .arg("-c:v")
+        .arg("copy")
         // Experimental is required for opus.
         .arg("-strict")
         .arg("-2")
         // Default audio codec is aac which has poor quality.
         .arg("-c:a")
         .arg(audio_codec)
-        // Try to avoid pauses.
-        .arg("-shortest")
         // Move some data to the beginning for faster playback start.
         .arg("-movflags")
         .arg("faststart")
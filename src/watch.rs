@@ -10,29 +10,30 @@ use notify::recommended_watcher;
 use notify::Event;
 use notify::Result;
 use notify::Watcher;
-use std::ffi::OsStr;
+use sha2::Digest;
+use sha2::Sha256;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::mpsc;
 
-/// Add a timestamp to the filename.
+/// Add a hash of the content of the file to the filename.
 ///
-/// This is used to bust the browser cache (force update).
-fn add_timestamp(filename: &OsStr, timestamp: u64) -> String {
-    let path = Path::new(filename);
+/// This is used to bust the browser cache (force update) for the files that
+/// changed while keeping the names of the other files the same.
+fn public_filename(path: &Path) -> String {
+    let content = std::fs::read(path).unwrap();
+    let hash = format!("{:x}", Sha256::digest(content));
     let stem = path.file_stem().unwrap().to_str().unwrap();
     let extension = path.extension().unwrap_or_default().to_str().unwrap_or("");
-    format!("{}_{}.{}", stem, timestamp, extension)
+    format!("{}_{}.{}", stem, &hash[..16], extension)
 }
 
-fn core_html(out_dir: &str, slide: &Slide, timestamp: u64, config: &Config) -> String {
+fn core_html(out_dir: &str, slide: &Slide, config: &Config) -> String {
     let image_path = crate::path::image_path(out_dir, slide);
-    let image_file = image_path.file_name().unwrap();
-    let image_file = add_timestamp(image_file, timestamp);
+    let image_file = public_filename(&image_path);
     let audio_ext = audio_format(config);
     let audio_path = crate::path::audio_path(out_dir, slide, &audio_ext);
-    let audio_file = audio_path.file_name().unwrap();
-    let audio_file = add_timestamp(audio_file, timestamp);
+    let audio_file = public_filename(&audio_path);
     format!(
         indoc::indoc! {"
         <div class='slide'>
@@ -48,17 +49,11 @@ fn core_html(out_dir: &str, slide: &Slide, timestamp: u64, config: &Config) -> S
     )
 }
 
-fn index(
-    args: &Arguments,
-    config: &Config,
-    slides: &[Slide],
-    timestamp: u64,
-    init: bool,
-) -> String {
+fn index(args: &Arguments, config: &Config, slides: &[Slide], init: bool) -> String {
     let out_dir = &args.out_dir;
     let core = slides
         .iter()
-        .map(|slide| core_html(out_dir, slide, timestamp, config))
+        .map(|slide| core_html(out_dir, slide, config))
         .collect::<Vec<_>>()
         .join("\n");
     let waiting_text = if init {
@@ -116,46 +111,39 @@ fn public_dir(args: &Arguments) -> PathBuf {
     public_path
 }
 
-fn build_index(args: &Arguments, config: &Config, slides: &[Slide], timestamp: u64, init: bool) {
-    let index = index(args, config, slides, timestamp, init);
+fn build_index(args: &Arguments, config: &Config, slides: &[Slide], init: bool) {
+    let index = index(args, config, slides, init);
     let path = public_dir(args).join("index.html");
     tracing::info!("Writing index.html");
     std::fs::write(path, index).unwrap();
 }
 
-/// Timestamp in unix seconds.
+/// Copy the images and audio into the public directory.
 ///
-/// Used for cache busting.
-fn timestamp() -> u64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
-}
-
-fn move_files_into_public(args: &Arguments, config: &Config, slides: &[Slide]) -> u64 {
+/// Files that did not change since the previous build are already there.
+/// Returns the names of the files in the public directory.
+fn move_files_into_public(args: &Arguments, config: &Config, slides: &[Slide]) -> Vec<String> {
     let public_path = public_dir(args);
     let out_dir = &args.out_dir;
+    let audio_ext = audio_format(config);
 
-    let timestamp = timestamp();
-
+    let mut filenames = Vec::new();
     for slide in slides {
         let image_path = crate::path::image_path(out_dir, slide);
-        let filename = image_path.file_name().unwrap();
-        let filename = add_timestamp(filename, timestamp);
-
-        std::fs::copy(image_path, public_path.join(filename)).unwrap();
-
-        let audio_ext = audio_format(config);
         let audio_path = crate::path::audio_path(out_dir, slide, &audio_ext);
-        let filename = audio_path.file_name().unwrap();
-        let filename = add_timestamp(filename, timestamp);
-        std::fs::copy(audio_path, public_path.join(filename)).unwrap();
+        for path in [image_path, audio_path] {
+            let filename = public_filename(&path);
+            let public_file = public_path.join(&filename);
+            if !public_file.exists() {
+                std::fs::copy(path, public_file).unwrap();
+            }
+            filenames.push(filename);
+        }
     }
-    timestamp
+    filenames
 }
 
-fn remove_old_files(args: &Arguments, timestamp: u64) {
+fn remove_old_files(args: &Arguments, filenames: &[String]) {
     let public_path = public_dir(args);
     for entry in std::fs::read_dir(public_path).unwrap() {
         let entry = entry.unwrap();
@@ -166,7 +154,7 @@ fn remove_old_files(args: &Arguments, timestamp: u64) {
         if let Some(extension) = path.extension() {
             if extension != "html" {
                 let filename = path.file_name().unwrap().to_str().unwrap();
-                if !filename.contains(&format!("_{}", timestamp)) {
+                if !filenames.iter().any(|name| name == filename) {
                     std::fs::remove_file(path).unwrap();
                 }
             }
@@ -207,13 +195,22 @@ async fn watch_build(watch_args: &WatchArgs, config: &Config, args: &Arguments)
     let release = false;
     let input = watch_args.input.clone();
     let audio_codec = None;
+    let burn_captions = false;
 
     let status = run_pre_typst(watch_args);
     if status == Status::Success {
-        let slides = build(input.clone(), config, args, release, audio_codec).await;
-        let timestamp = move_files_into_public(args, config, &slides);
-        build_index(args, config, &slides, timestamp, false);
-        remove_old_files(args, timestamp);
+        let slides = build(
+            input.clone(),
+            config,
+            args,
+            release,
+            audio_codec,
+            burn_captions,
+        )
+        .await;
+        let filenames = move_files_into_public(args, config, &slides);
+        build_index(args, config, &slides, false);
+        remove_old_files(args, &filenames);
     }
 }
 
@@ -261,8 +258,7 @@ pub async fn watch(watch_args: &WatchArgs, config: &Config, args: &Arguments) {
     }
 
     let slides = [];
-    let timestamp = timestamp();
-    build_index(args, config, &slides, timestamp, true);
+    build_index(args, config, &slides, true);
     spawn_server(watch_args, args);
     watch_build(watch_args, config, args).await;
 
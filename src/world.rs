@@ -0,0 +1,248 @@
+//! In-process Typst compilation.
+//!
+//! Used instead of the `typst` CLI when trv is built with the `embed-typst`
+//! feature.
+use chrono::Datelike;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use typst::diag::FileError;
+use typst::diag::FileResult;
+use typst::diag::SourceDiagnostic;
+use typst::foundations::Bytes;
+use typst::foundations::Datetime;
+use typst::foundations::Label;
+use typst::foundations::Selector;
+use typst::layout::PagedDocument;
+use typst::syntax::FileId;
+use typst::syntax::Source;
+use typst::syntax::VirtualPath;
+use typst::text::Font;
+use typst::text::FontBook;
+use typst::utils::LazyHash;
+use typst::utils::PicoStr;
+use typst::Library;
+use typst::World;
+use typst_kit::download::Downloader;
+use typst_kit::download::ProgressSink;
+use typst_kit::fonts::FontSearcher;
+use typst_kit::fonts::FontSlot;
+use typst_kit::package::PackageStorage;
+
+/// Pixels per point for 300 pixels per inch, like `typst compile --ppi=300`.
+const PIXEL_PER_PT: f32 = 300.0 / 72.0;
+
+/// Content of a file that was read during compilation.
+struct FileSlot {
+    bytes: Bytes,
+    /// The parsed file, if the file was used as a Typst source.
+    source: Option<Source>,
+}
+
+/// Compilation environment for a presentation.
+///
+/// The world is kept between builds, so that fonts are only searched once and
+/// unchanged files don't have to be parsed again.
+struct SlidesWorld {
+    input: PathBuf,
+    root: PathBuf,
+    main: FileId,
+    library: LazyHash<Library>,
+    book: LazyHash<FontBook>,
+    fonts: Vec<FontSlot>,
+    packages: PackageStorage,
+    files: Mutex<HashMap<FileId, FileSlot>>,
+    /// The last compiled document.
+    ///
+    /// Reused when none of the files changed since the compilation.
+    document: Option<PagedDocument>,
+}
+
+impl SlidesWorld {
+    fn new(input: &Path) -> Self {
+        let input = input.canonicalize().unwrap();
+        let root = input.parent().unwrap().to_path_buf();
+        let vpath = VirtualPath::within_root(&input, &root).unwrap();
+        let fonts = FontSearcher::new().include_system_fonts(true).search();
+        let user_agent = concat!("trv/", env!("CARGO_PKG_VERSION"));
+        Self {
+            input,
+            root,
+            main: FileId::new(None, vpath),
+            library: LazyHash::new(Library::builder().build()),
+            book: LazyHash::new(fonts.book),
+            fonts: fonts.fonts,
+            packages: PackageStorage::new(None, None, Downloader::new(user_agent)),
+            files: Mutex::new(HashMap::new()),
+            document: None,
+        }
+    }
+
+    fn path(&self, id: FileId) -> FileResult<PathBuf> {
+        let root = match id.package() {
+            Some(spec) => self.packages.prepare_package(spec, &mut ProgressSink)?,
+            None => self.root.clone(),
+        };
+        id.vpath().resolve(&root).ok_or(FileError::AccessDenied)
+    }
+
+    fn read(&self, id: FileId) -> FileResult<Bytes> {
+        let path = self.path(id)?;
+        let content = std::fs::read(&path).map_err(|e| FileError::from_io(e, &path))?;
+        Ok(Bytes::new(content))
+    }
+
+    /// Whether any of the files that were read during the last compilation
+    /// changed on disk.
+    fn changed(&self) -> bool {
+        let files = self.files.lock().unwrap();
+        files
+            .iter()
+            .any(|(id, slot)| self.read(*id).ok().as_ref() != Some(&slot.bytes))
+    }
+
+    fn compile(&mut self) -> PagedDocument {
+        if let Some(document) = &self.document {
+            if !self.changed() {
+                return document.clone();
+            }
+        }
+        let result = typst::compile::<PagedDocument>(self);
+        for warning in &result.warnings {
+            tracing::warn!("{}", self.format_diagnostic(warning));
+        }
+        let document = match result.output {
+            Ok(document) => document,
+            Err(errors) => {
+                for error in &errors {
+                    tracing::error!("{}", self.format_diagnostic(error));
+                }
+                tracing::error!("Failed to compile {}", self.input.display());
+                std::process::exit(1);
+            }
+        };
+        // Drop cached results that were not used for a while.
+        typst::comemo::evict(10);
+        self.document = Some(document.clone());
+        document
+    }
+
+    /// Format the diagnostic with the location in the file, for example,
+    /// `presentation.typ:12:5: unknown variable: foo`.
+    fn format_diagnostic(&self, diagnostic: &SourceDiagnostic) -> String {
+        let mut message = diagnostic.message.to_string();
+        for hint in &diagnostic.hints {
+            message.push_str(&format!(" (hint: {hint})"));
+        }
+        let Some(id) = diagnostic.span.id() else {
+            return message;
+        };
+        let Ok(source) = self.source(id) else {
+            return message;
+        };
+        let path = id.vpath().as_rootless_path().display();
+        match source.range(diagnostic.span) {
+            Some(range) => {
+                let line = source.byte_to_line(range.start).unwrap_or(0) + 1;
+                let column = source.byte_to_column(range.start).unwrap_or(0) + 1;
+                format!("{path}:{line}:{column}: {message}")
+            }
+            None => format!("{path}: {message}"),
+        }
+    }
+}
+
+impl World for SlidesWorld {
+    fn library(&self) -> &LazyHash<Library> {
+        &self.library
+    }
+
+    fn book(&self) -> &LazyHash<FontBook> {
+        &self.book
+    }
+
+    fn main(&self) -> FileId {
+        self.main
+    }
+
+    fn source(&self, id: FileId) -> FileResult<Source> {
+        let bytes = self.file(id)?;
+        let mut files = self.files.lock().unwrap();
+        let slot = files.get_mut(&id).unwrap();
+        let text = std::str::from_utf8(&bytes).map_err(|_| FileError::InvalidUtf8)?;
+        match &mut slot.source {
+            Some(source) => {
+                if source.text() != text {
+                    // Only reparses the part of the file that changed.
+                    source.replace(text);
+                }
+            }
+            None => slot.source = Some(Source::new(id, text.to_string())),
+        }
+        Ok(slot.source.clone().unwrap())
+    }
+
+    fn file(&self, id: FileId) -> FileResult<Bytes> {
+        let bytes = self.read(id)?;
+        let mut files = self.files.lock().unwrap();
+        let slot = files.entry(id).or_insert_with(|| FileSlot {
+            bytes: bytes.clone(),
+            source: None,
+        });
+        slot.bytes = bytes.clone();
+        Ok(bytes)
+    }
+
+    fn font(&self, index: usize) -> Option<Font> {
+        self.fonts.get(index)?.get()
+    }
+
+    fn today(&self, offset: Option<i64>) -> Option<Datetime> {
+        let now = chrono::Local::now();
+        let date = match offset {
+            None => now.date_naive(),
+            Some(hours) => (now.naive_utc() + chrono::Duration::hours(hours)).date(),
+        };
+        Datetime::from_ymd(date.year(), date.month() as u8, date.day() as u8)
+    }
+}
+
+static WORLD: Mutex<Option<SlidesWorld>> = Mutex::new(None);
+
+/// Compile the presentation.
+///
+/// The notes and the images are both taken from the same compilation. When
+/// the input is the same as in the previous call, the world is reused.
+fn compile(input: &Path) -> PagedDocument {
+    let mut world = WORLD.lock().unwrap();
+    let input_path = input.canonicalize().unwrap();
+    if world.as_ref().is_none_or(|world| world.input != input_path) {
+        *world = Some(SlidesWorld::new(input));
+    }
+    world.as_mut().unwrap().compile()
+}
+
+/// Values of the `<pdfpc>` metadata, like `typst query <input> <pdfpc> --field=value`.
+pub(crate) fn query_presenter_notes(input: &str) -> Value {
+    let document = compile(Path::new(input));
+    let selector = Selector::Label(Label::new(PicoStr::intern("pdfpc")));
+    let values = document
+        .introspector
+        .query(&selector)
+        .iter()
+        .filter_map(|content| content.get_by_name("value").ok())
+        .collect::<Vec<_>>();
+    serde_json::to_value(values).unwrap()
+}
+
+/// Render the pages to `{p}.png` in the directory, like `typst compile --format=png --ppi=300`.
+pub(crate) fn render_pages(input: &Path, dir: &Path) {
+    let document = compile(input);
+    for (i, page) in document.pages.iter().enumerate() {
+        let pixmap = typst_render::render(page, PIXEL_PER_PT);
+        let png = pixmap.encode_png().unwrap();
+        std::fs::write(dir.join(format!("{}.png", i + 1)), png).unwrap();
+    }
+}
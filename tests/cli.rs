@@ -25,12 +25,7 @@ fn test_cache() -> Result<(), Box<dyn std::error::Error>> {
     let key = common::load_key(&provider);
 
     // Not deleting the dir to avoid cargo watch going into an infinite loop.
-    let files = vec![
-        "audio/1.mp3",
-        "audio/2.mp3",
-        "audio/1.audio.cache_key",
-        "out.mp4",
-    ];
+    let files = vec!["audio/1.mp3", "audio/2.mp3", "out.mp4"];
     for file in &files {
         let path = Path::new(out_dir).join(file);
         if path.exists() {
@@ -38,10 +33,15 @@ fn test_cache() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    // A fresh cache directory to make sure that the first run is not cached.
+    let cache_dir = tempfile::tempdir()?;
+    let cache_dir = cache_dir.path().to_str().unwrap();
+
     let mut cmd = bin();
     cmd.env("DEEPINFRA_KEY", &key);
     cmd.arg("--verbose");
     cmd.arg(format!("--out-dir={}", out_dir));
+    cmd.arg(format!("--cache-dir={}", cache_dir));
     cmd.arg("build");
     cmd.arg("tests/test_cache.typ");
     cmd.assert()
@@ -60,6 +60,7 @@ fn test_cache() -> Result<(), Box<dyn std::error::Error>> {
     cmd.env("DEEPINFRA_KEY", key);
     cmd.arg("--verbose");
     cmd.arg(format!("--out-dir={}", out_dir));
+    cmd.arg(format!("--cache-dir={}", cache_dir));
     cmd.arg("build");
     cmd.arg("tests/test_cache.typ");
     cmd.assert()
@@ -73,17 +74,18 @@ fn test_cache() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 #[test]
-fn openai_compatible_provider() -> Result<(), Box<dyn std::error::Error>> {
-    let out_dir = Path::new("tests").join("_compatible_out");
+fn mock_provider() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = Path::new("tests").join("_mock_out");
     let out_dir = out_dir.to_str().unwrap();
     println!("out_dir: {out_dir}");
 
     // Not deleting the dir to avoid cargo watch going into an infinite loop.
     let files = vec![
-        "audio/1.wav",
-        "audio/2.wav",
-        "audio/1.audio.cache_key",
+        "audio/1.mp3",
+        "audio/2.mp3",
         "out.mp4",
+        "out.srt",
+        "out.vtt",
     ];
     for file in &files {
         let path = Path::new(out_dir).join(file);
@@ -92,6 +94,56 @@ fn openai_compatible_provider() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    // A fresh cache directory to make sure that the first run is not cached.
+    let cache_dir = tempfile::tempdir()?;
+    let cache_dir = cache_dir.path().to_str().unwrap();
+
+    let mut cmd = bin();
+    cmd.arg(format!("--out-dir={}", out_dir));
+    cmd.arg(format!("--cache-dir={}", cache_dir));
+    cmd.arg("--verbose");
+    cmd.arg("build");
+    cmd.arg("tests/test_mock.typ");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Skipping").not());
+
+    for file in &files {
+        let path = Path::new(out_dir).join(file);
+        assert!(path.exists(), "file {} does not exist", file);
+    }
+
+    let mut cmd = bin();
+    cmd.arg(format!("--out-dir={}", out_dir));
+    cmd.arg(format!("--cache-dir={}", cache_dir));
+    cmd.arg("--verbose");
+    cmd.arg("build");
+    cmd.arg("tests/test_mock.typ");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Slide 1: Skipping audio generation due to cache",
+        ))
+        .stdout(predicate::str::contains("Slide 1: Reusing video segment"));
+
+    Ok(())
+}
+
+#[test]
+fn openai_compatible_provider() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = Path::new("tests").join("_compatible_out");
+    let out_dir = out_dir.to_str().unwrap();
+    println!("out_dir: {out_dir}");
+
+    // Not deleting the dir to avoid cargo watch going into an infinite loop.
+    let files = vec!["audio/1.wav", "audio/2.wav", "out.mp4"];
+    for file in &files {
+        let path = Path::new(out_dir).join(file);
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+    }
+
     let mut cmd = bin();
     cmd.arg(format!("--out-dir={}", out_dir));
     cmd.arg("--verbose");
@@ -115,12 +167,7 @@ fn elevenlabs_provider() -> Result<(), Box<dyn std::error::Error>> {
     let key = common::load_key(&Provider::ElevenLabs);
 
     // Not deleting the dir to avoid cargo watch going into an infinite loop.
-    let files = vec![
-        "audio/1.mp3",
-        "audio/2.mp3",
-        "audio/1.audio.cache_key",
-        "out.mp4",
-    ];
+    let files = vec!["audio/1.mp3", "audio/2.mp3", "out.mp4"];
     for file in &files {
         let path = Path::new(out_dir).join(file);
         if path.exists() {
@@ -157,12 +204,7 @@ fn google_provider() -> Result<(), Box<dyn std::error::Error>> {
     let key = common::load_key(&Provider::Google);
 
     // Not deleting the dir to avoid cargo watch going into an infinite loop.
-    let files = vec![
-        "audio/1.mp3",
-        "audio/2.mp3",
-        "audio/1.audio.cache_key",
-        "out.mp4",
-    ];
+    let files = vec!["audio/1.mp3", "audio/2.mp3", "out.mp4"];
     for file in &files {
         let path = Path::new(out_dir).join(file);
         if path.exists() {
@@ -262,13 +304,13 @@ fn test_duration_matches() -> Result<(), Box<dyn std::error::Error>> {
     let video_path = video_path.to_str().unwrap();
     let audio_path = Path::new(out_dir).join("out.mp3");
     let audio_path = audio_path.to_str().unwrap();
-    convert_to_mp3(&video_path, &audio_path);
+    convert_to_mp3(video_path, audio_path);
 
-    let video_duration = probe_duration(&video_path).unwrap();
+    let video_duration = probe_duration(video_path).unwrap();
     println!("video_duration: {video_duration}");
     let video_duration = duration_as_seconds(&video_duration);
     println!("video_duration: {video_duration} seconds");
-    let audio_duration = probe_duration(&audio_path).unwrap();
+    let audio_duration = probe_duration(audio_path).unwrap();
     println!("audio_duration: {audio_duration}");
     let audio_duration = duration_as_seconds(&audio_duration);
     println!("audio_duration: {audio_duration} seconds");
@@ -70,7 +70,7 @@ fn clean_content(content: &str) -> String {
 fn get_script_name(video_link: &str) -> String {
     let video_name = video_link
         .split('/')
-        .last()
+        .next_back()
         .unwrap()
         .trim_end_matches(')')
         .trim_end_matches(".mp4");
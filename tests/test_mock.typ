@@ -0,0 +1,41 @@
+#import "@preview/polylux:0.4.0": *
+
+#set page(paper: "presentation-16-9")
+#set text(size: 25pt)
+
+// The mock provider generates silent audio, so this file can be built without
+// an API key or network access.
+
+// --- trv config:
+// provider = "mock"
+// voice = "none"
+// audio_format = "mp3"
+// ---
+
+#slide[
+    \
+    #align(center)[Code examples or code videos?]
+
+    #toolbox.pdfpc.speaker-note(
+        ```md
+        What if you could show code in a video?
+        ```
+    )
+]
+
+#slide[
+    #set text(size: 20pt)
+
+    ```rust
+    #[tokio::main]
+    async fn main() {
+        println!("Hello, world!");
+    }
+    ```
+
+    #toolbox.pdfpc.speaker-note(
+        ```md
+        For example, take this code.
+        ```
+    )
+]